@@ -0,0 +1,529 @@
+use futures::future::{self, Either, Future};
+use futures::stream::{self, Stream};
+use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::search::{self, SearchQuery, Source};
+use super::transport::{self, AsyncHttpTransport, AsyncTransport, HttpTransport, Transport};
+use super::{
+    parse_html_with_options, parse_rss_with_options, parse_view_html, NyaasiTorrentDetails,
+    ParseError, ParseOptions, Results,
+};
+
+const DEFAULT_USER_AGENT: &str = concat!("nyaasi-scraper/", env!("CARGO_PKG_VERSION"));
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Error returned when fetching or parsing a page fails
+#[derive(Debug)]
+pub enum Error {
+    /// The HTTP client couldn't be built
+    Http(reqwest::Error),
+    /// The transport couldn't fetch the page
+    Transport(transport::Error),
+    /// The site answered with an error status
+    Status { url: String, status: u16 },
+    /// The page couldn't be parsed
+    Parse(ParseError),
+    /// The search query is invalid
+    Query(search::Error),
+    /// The user agent isn't a valid header value
+    UserAgent(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "Failed to create HTTP client: {}", e),
+            Error::Transport(e) => write!(f, "{}", e),
+            Error::Status { url, status } => write!(f, "{} returned status {}", url, status),
+            Error::Parse(e) => write!(f, "{}", e),
+            Error::Query(e) => write!(f, "{}", e),
+            Error::UserAgent(ua) => write!(f, "Invalid user agent {}", ua),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Error {
+        Error::Http(e)
+    }
+}
+
+impl From<transport::Error> for Error {
+    fn from(e: transport::Error) -> Error {
+        Error::Transport(e)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Error {
+        Error::Parse(e)
+    }
+}
+
+impl From<search::Error> for Error {
+    fn from(e: search::Error) -> Error {
+        Error::Query(e)
+    }
+}
+
+/// Builder for NyaaClient and AsyncNyaaClient
+#[derive(Debug, Clone)]
+pub struct ClientBuilder {
+    source: Source,
+    base_url: Option<String>,
+    user_agent: String,
+    timeout: Option<Duration>,
+    options: ParseOptions,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> ClientBuilder {
+        ClientBuilder {
+            source: Source::Nyaasi,
+            base_url: None,
+            user_agent: DEFAULT_USER_AGENT.to_owned(),
+            timeout: Some(DEFAULT_TIMEOUT),
+            options: ParseOptions::default(),
+        }
+    }
+}
+
+impl ClientBuilder {
+    /// Sets the site used for entry pages, and for searches without a base url.
+    /// Defaults to nyaa.si.
+    pub fn source(mut self, source: Source) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Sets the url to use instead of the source's url, for mirrors. Searches
+    /// with their own base url keep it.
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.base_url = Some(base_url.trim_end_matches('/').to_owned());
+        self
+    }
+
+    /// Sets the user agent sent with requests
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = user_agent.to_owned();
+        self
+    }
+
+    /// Sets the timeout of each request, or disables it if None. Defaults to
+    /// 30 seconds.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the options used to parse search pages
+    pub fn parse_options(mut self, options: ParseOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Builds a blocking client
+    pub fn build(self) -> Result<NyaaClient, Error> {
+        let http = reqwest::Client::builder()
+            .default_headers(self.headers()?)
+            .timeout(self.timeout)
+            .build()?;
+        Ok(self.build_with_transport(HttpTransport::new(http)))
+    }
+
+    /// Builds a blocking client that fetches pages with the given transport.
+    /// The user agent and timeout are left to the transport.
+    pub fn build_with_transport<T: Transport>(self, transport: T) -> NyaaClient<T> {
+        NyaaClient {
+            transport: transport,
+            config: self.into_config(),
+        }
+    }
+
+    /// Builds an async client
+    pub fn build_async(self) -> Result<AsyncNyaaClient, Error> {
+        let mut builder = reqwest::r#async::Client::builder().default_headers(self.headers()?);
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        let http = builder.build()?;
+        Ok(self.build_async_with_transport(AsyncHttpTransport::new(http)))
+    }
+
+    /// Builds an async client that fetches pages with the given transport.
+    /// The user agent and timeout are left to the transport.
+    pub fn build_async_with_transport<T: AsyncTransport>(self, transport: T) -> AsyncNyaaClient<T> {
+        AsyncNyaaClient {
+            transport: Arc::new(transport),
+            config: Arc::new(self.into_config()),
+        }
+    }
+
+    fn headers(&self) -> Result<HeaderMap, Error> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            USER_AGENT,
+            HeaderValue::from_str(&self.user_agent)
+                .map_err(|_| Error::UserAgent(self.user_agent.clone()))?,
+        );
+        Ok(headers)
+    }
+
+    fn into_config(self) -> Config {
+        let source = self.source;
+        Config {
+            override_base_url: self.base_url.is_some(),
+            site: self
+                .base_url
+                .unwrap_or_else(|| source.base_url().to_owned()),
+            options: self.options,
+        }
+    }
+}
+
+/// Settings shared by the blocking and async clients
+#[derive(Debug)]
+struct Config {
+    site: String,
+    override_base_url: bool,
+    options: ParseOptions,
+}
+
+impl Config {
+    /// Returns the url of a search, and whether it's a RSS feed
+    fn search_url(&self, query: &SearchQuery) -> Result<(String, bool), Error> {
+        let url = if query.base_url.is_none() && self.override_base_url {
+            query.clone().base_url(&self.site).to_url()?
+        } else {
+            query.to_url()?
+        };
+        Ok((url.into(), query.rss))
+    }
+
+    fn view_url(&self, id: u64) -> String {
+        format!("{}/view/{}", self.site, id)
+    }
+
+    fn parse_search(
+        &self,
+        url: &str,
+        status: u16,
+        body: &str,
+        rss: bool,
+    ) -> Result<Results, Error> {
+        check_status(url, status)?;
+        if rss {
+            Ok(parse_rss_with_options(body, &self.options)?)
+        } else {
+            Ok(parse_html_with_options(body, url, &self.options)?)
+        }
+    }
+
+    fn parse_view(
+        &self,
+        url: &str,
+        status: u16,
+        body: &str,
+    ) -> Result<NyaasiTorrentDetails, Error> {
+        check_status(url, status)?;
+        Ok(parse_view_html(body, url)?)
+    }
+}
+
+fn check_status(url: &str, status: u16) -> Result<(), Error> {
+    if (200..300).contains(&status) {
+        Ok(())
+    } else {
+        Err(Error::Status {
+            url: url.to_owned(),
+            status: status,
+        })
+    }
+}
+
+fn next_url(results: &Results) -> Option<String> {
+    results
+        .pagination
+        .as_ref()
+        .and_then(|p| p.next.as_ref())
+        .map(|p| p.url.clone())
+}
+
+/// Blocking client that fetches and parses pages.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nyaasi_scraper::client::NyaaClient;
+/// use nyaasi_scraper::search::{SearchQuery, Source};
+///
+/// let client = NyaaClient::new().unwrap();
+/// let results = client.search(&SearchQuery::new(Source::Nyaasi).query("some show")).unwrap();
+/// if let Some(older) = client.next_page(&results).unwrap() {
+///     println!("{} more entries", older.entries.len());
+/// }
+/// ```
+#[derive(Debug)]
+pub struct NyaaClient<T = HttpTransport> {
+    transport: T,
+    config: Config,
+}
+
+impl NyaaClient {
+    /// Creates a client with the default settings
+    pub fn new() -> Result<NyaaClient, Error> {
+        ClientBuilder::default().build()
+    }
+
+    /// Returns a builder to customize the client
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::default()
+    }
+}
+
+impl<T: Transport> NyaaClient<T> {
+    /// Returns the transport used to fetch pages
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Fetches and parses the results of a search
+    pub fn search(&self, query: &SearchQuery) -> Result<Results, Error> {
+        let (url, rss) = self.config.search_url(query)?;
+        let (status, body) = self.get(&url)?;
+        self.config.parse_search(&url, status, &body, rss)
+    }
+
+    /// Fetches and parses the page of an entry
+    pub fn view(&self, id: u64) -> Result<NyaasiTorrentDetails, Error> {
+        let url = self.config.view_url(id);
+        let (status, body) = self.get(&url)?;
+        self.config.parse_view(&url, status, &body)
+    }
+
+    /// Fetches and parses the page after the given results, or returns None
+    /// if they're from the last page
+    pub fn next_page(&self, results: &Results) -> Result<Option<Results>, Error> {
+        match next_url(results) {
+            None => Ok(None),
+            Some(url) => {
+                let (status, body) = self.get(&url)?;
+                self.config
+                    .parse_search(&url, status, &body, false)
+                    .map(Some)
+            }
+        }
+    }
+
+    fn get(&self, url: &str) -> Result<(u16, String), Error> {
+        let response = self.transport.get(url)?;
+        Ok((response.status, response.body))
+    }
+}
+
+/// Async version of NyaaClient, returning futures instead of blocking.
+///
+/// The futures of the default transport must be run on a tokio runtime.
+/// Requests aren't retried, as RetryTransport only wraps blocking transports.
+#[derive(Debug)]
+pub struct AsyncNyaaClient<T = AsyncHttpTransport> {
+    transport: Arc<T>,
+    config: Arc<Config>,
+}
+
+impl<T> Clone for AsyncNyaaClient<T> {
+    fn clone(&self) -> AsyncNyaaClient<T> {
+        AsyncNyaaClient {
+            transport: self.transport.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+impl AsyncNyaaClient {
+    /// Creates a client with the default settings
+    pub fn new() -> Result<AsyncNyaaClient, Error> {
+        ClientBuilder::default().build_async()
+    }
+}
+
+impl<T: AsyncTransport> AsyncNyaaClient<T> {
+    /// Returns the transport used to fetch pages
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Fetches and parses the results of a search
+    pub fn search(&self, query: &SearchQuery) -> impl Future<Item = Results, Error = Error> {
+        let config = self.config.clone();
+        match config.search_url(query) {
+            Err(e) => Either::A(future::err(e)),
+            Ok((url, rss)) => Either::B(
+                self.get(url.clone())
+                    .and_then(move |(status, body)| config.parse_search(&url, status, &body, rss)),
+            ),
+        }
+    }
+
+    /// Fetches and parses the page of an entry
+    pub fn view(&self, id: u64) -> impl Future<Item = NyaasiTorrentDetails, Error = Error> {
+        let config = self.config.clone();
+        let url = config.view_url(id);
+        self.get(url.clone())
+            .and_then(move |(status, body)| config.parse_view(&url, status, &body))
+    }
+
+    /// Fetches and parses the page after the given results, or returns None
+    /// if they're from the last page
+    pub fn next_page(
+        &self,
+        results: &Results,
+    ) -> impl Future<Item = Option<Results>, Error = Error> {
+        let config = self.config.clone();
+        match next_url(results) {
+            None => Either::A(future::ok(None)),
+            Some(url) => Either::B(self.get(url.clone()).and_then(move |(status, body)| {
+                config.parse_search(&url, status, &body, false).map(Some)
+            })),
+        }
+    }
+
+    /// Returns a stream of the results of each page of a search, starting at
+    /// the query's page and following the next page links until the last page.
+    /// The stream ends after the first error.
+    pub fn pages(&self, query: &SearchQuery) -> impl Stream<Item = Results, Error = Error> {
+        let client = self.clone();
+        let first = self.config.search_url(query);
+        stream::unfold(Some(first), move |state| {
+            let (url, rss) = match state? {
+                Ok(first) => first,
+                Err(e) => return Some(Either::A(future::err(e))),
+            };
+            let config = client.config.clone();
+            Some(Either::B(client.get(url.clone()).and_then(
+                move |(status, body)| {
+                    let results = config.parse_search(&url, status, &body, rss)?;
+                    let next = next_url(&results).map(|url| Ok((url, false)));
+                    Ok((results, next))
+                },
+            )))
+        })
+    }
+
+    fn get(&self, url: String) -> impl Future<Item = (u16, String), Error = Error> {
+        self.transport
+            .get_async(&url)
+            .map(|response| (response.status, response.body))
+            .map_err(Error::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{MockTransport, Response};
+
+    #[test]
+    fn test_search_url() {
+        let config = ClientBuilder::default().into_config();
+        let query = SearchQuery::new(Source::Sukebei).query("a");
+        assert_eq!(
+            config.search_url(&query).unwrap(),
+            (
+                "https://sukebei.nyaa.si/?f=0&c=0_0&p=1&q=a".to_owned(),
+                false
+            )
+        );
+    }
+
+    #[test]
+    fn test_base_url() {
+        let config = ClientBuilder::default()
+            .base_url("https://mirror.example/")
+            .into_config();
+        let query = SearchQuery::new(Source::Nyaasi).rss(true);
+        assert_eq!(
+            config.search_url(&query).unwrap(),
+            (
+                "https://mirror.example/?f=0&c=0_0&p=1&q=&page=rss".to_owned(),
+                true
+            )
+        );
+        // queries with their own base url keep it
+        let query = query.base_url("https://other.example");
+        assert_eq!(
+            config.search_url(&query).unwrap().0,
+            "https://other.example/?f=0&c=0_0&p=1&q=&page=rss"
+        );
+        assert_eq!(config.view_url(1), "https://mirror.example/view/1");
+    }
+
+    #[test]
+    fn test_mock_transport() {
+        let html = include_str!("../tests/fixtures/empty.html");
+        let client = ClientBuilder::default().build_with_transport(
+            MockTransport::new()
+                .with(
+                    "https://nyaa.si/?f=0&c=0_0&p=1&q=a",
+                    Response::new(200, html),
+                )
+                .with("https://nyaa.si/view/1", Response::new(404, "")),
+        );
+        let results = client
+            .search(&SearchQuery::new(Source::Nyaasi).query("a"))
+            .unwrap();
+        assert!(results.entries.is_empty());
+        assert_eq!(client.next_page(&results).unwrap(), None);
+        match client.view(1) {
+            Err(Error::Status { status: 404, .. }) => {}
+            other => panic!("Expected status error, got {:?}", other),
+        }
+        match client.view(2) {
+            Err(Error::Transport(transport::Error::Unmatched { .. })) => {}
+            other => panic!("Expected transport error, got {:?}", other),
+        }
+        assert_eq!(client.transport().requests().len(), 3);
+    }
+
+    #[test]
+    fn test_async_mock_transport() {
+        let html = include_str!("../tests/fixtures/search.html");
+        let first = "https://nyaa.si/?f=0&c=0_0&p=2&q=show";
+        let client = ClientBuilder::default().build_async_with_transport(
+            MockTransport::new()
+                .with(first, Response::new(200, html))
+                .with("https://nyaa.si/view/1", Response::new(404, "")),
+        );
+        let query = SearchQuery::new(Source::Nyaasi).query("show").page(2);
+        let results = client.search(&query).wait().unwrap();
+        assert_eq!(results.entries.len(), 3);
+        match client.view(1).wait() {
+            Err(Error::Status { status: 404, .. }) => {}
+            other => panic!("Expected status error, got {:?}", other),
+        }
+        // the second page isn't available, so the stream ends with an error
+        let mut pages = client.pages(&query).wait();
+        assert_eq!(pages.next().unwrap().unwrap(), results);
+        match pages.next() {
+            Some(Err(Error::Transport(transport::Error::Unmatched { url }))) => {
+                assert_eq!(url, "https://nyaa.si/?f=0&c=0_0&q=show&p=3")
+            }
+            other => panic!("Expected transport error, got {:?}", other),
+        }
+        assert_eq!(client.transport().requests().len(), 4);
+    }
+
+    #[test]
+    fn test_status() {
+        let config = ClientBuilder::default().into_config();
+        match config.parse_search("https://nyaa.si/", 503, "", false) {
+            Err(Error::Status { status: 503, .. }) => {}
+            other => panic!("Expected status error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,306 @@
+use std::fmt;
+
+/// Error returned when a date string can't be parsed
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The string doesn't follow the expected format
+    Format(String),
+    /// One of the components of the date is out of range (eg month 13 or
+    /// year 10000)
+    OutOfRange(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Format(d) => write!(f, "Date {} doesn't match the expected format", d),
+            Error::OutOfRange(d) => write!(f, "Date {} is out of range", d),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Parses a date string such as "2019-09-16 12:34" to an unix timestamp in seconds.
+///
+/// Dates are assumed to be in UTC. Seconds and a trailing " UTC" are optional.
+/// Years must have at most four digits.
+///
+/// # Examples
+///
+/// ```
+/// use nyaasi_scraper::date_parser;
+///
+/// let raw = "2019-09-16 12:34";
+/// let parsed = date_parser::parse(raw);
+///
+/// assert_eq!(parsed, Ok(1568637240));
+/// ```
+pub fn parse(date: &str) -> Result<i64, Error> {
+    let format_error = || Error::Format(date.to_owned());
+    let trimmed = date.trim();
+    let trimmed = trimmed.trim_end_matches(" UTC");
+
+    let mut parts = trimmed.split(' ');
+    let (day, time) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(day), Some(time), None) => (day, time),
+        _ => return Err(format_error()),
+    };
+
+    let day = numbers(day, '-').ok_or_else(format_error)?;
+    let time = numbers(time, ':').ok_or_else(format_error)?;
+    let (year, month, day) = match day.as_slice() {
+        [y, m, d] => (*y, *m, *d),
+        _ => return Err(format_error()),
+    };
+    let (hour, minute, second) = match time.as_slice() {
+        [h, m] => (*h, *m, 0),
+        [h, m, s] => (*h, *m, *s),
+        _ => return Err(format_error()),
+    };
+
+    if !(0..=9999).contains(&year)
+        || !(1..=12).contains(&month)
+        || !(1..=days_in_month(year, month)).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return Err(Error::OutOfRange(date.to_owned()));
+    }
+
+    Ok(days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+/// Parses a RFC 2822 date string, as used by RSS feeds, to an unix timestamp in seconds.
+///
+/// # Examples
+///
+/// ```
+/// use nyaasi_scraper::date_parser;
+///
+/// let raw = "Mon, 16 Sep 2019 12:34:00 -0000";
+/// let parsed = date_parser::parse_rfc2822(raw);
+///
+/// assert_eq!(parsed, Ok(1568637240));
+/// ```
+pub fn parse_rfc2822(date: &str) -> Result<i64, Error> {
+    let format_error = || Error::Format(date.to_owned());
+    let trimmed = date.trim();
+    // the day of the week is optional and redundant
+    let trimmed = match trimmed.find(',') {
+        Some(idx) => &trimmed[idx + 1..],
+        None => trimmed,
+    };
+
+    let mut parts = trimmed.split_whitespace();
+    let (day, month, year, time, zone) = match (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    ) {
+        (Some(d), Some(m), Some(y), Some(t), Some(z), None) => (d, m, y, t, z),
+        _ => return Err(format_error()),
+    };
+
+    let month = MONTHS
+        .iter()
+        .position(|m| m.eq_ignore_ascii_case(month))
+        .ok_or_else(format_error)? as i64
+        + 1;
+    let offset = match zone {
+        "GMT" | "UT" | "UTC" | "Z" => 0,
+        _ => {
+            let sign = match zone.chars().next() {
+                Some('+') => 1,
+                Some('-') => -1,
+                _ => return Err(format_error()),
+            };
+            let digits = numbers(&zone[1..], ':')
+                .filter(|_| zone.len() == 5)
+                .and_then(|n| n.first().cloned())
+                .ok_or_else(format_error)?;
+            sign * ((digits / 100) * 3600 + (digits % 100) * 60)
+        }
+    };
+
+    let local = parse(&format!("{}-{}-{} {}", year, month, day, time)).map_err(|e| match e {
+        Error::Format(_) => format_error(),
+        Error::OutOfRange(_) => Error::OutOfRange(date.to_owned()),
+    })?;
+    Ok(local - offset)
+}
+
+/// Formats an unix timestamp in seconds as "YYYY-MM-DD HH:MM", the format
+/// used for dates on the site. This is the inverse of parse().
+///
+/// # Examples
+///
+/// ```
+/// use nyaasi_scraper::date_parser;
+///
+/// assert_eq!(date_parser::format(1568637240), "2019-09-16 12:34");
+/// ```
+pub fn format(timestamp: i64) -> String {
+    let days = timestamp.div_euclid(86400);
+    let seconds = timestamp.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60
+    )
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+fn numbers(s: &str, separator: char) -> Option<Vec<i64>> {
+    s.split(separator)
+        .map(|n| {
+            if n.is_empty() || !n.bytes().all(|b| b.is_ascii_digit()) {
+                None
+            } else {
+                n.parse::<i64>().ok()
+            }
+        })
+        .collect()
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Number of days since 1970-01-01 of a date in the proleptic gregorian calendar.
+/// See http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Inverse of days_from_civil().
+/// See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = if days >= 0 { days } else { days - 146096 } / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_epoch() {
+        assert_eq!(Ok(0), parse("1970-01-01 00:00"));
+    }
+
+    #[test]
+    fn test_minutes() {
+        assert_eq!(Ok(1568637240), parse("2019-09-16 12:34"));
+    }
+
+    #[test]
+    fn test_seconds_and_suffix() {
+        assert_eq!(Ok(1568637296), parse("2019-09-16 12:34:56 UTC"));
+    }
+
+    #[test]
+    fn test_leap_day() {
+        assert_eq!(Ok(951782400), parse("2000-02-29 00:00"));
+        assert_eq!(
+            Err(Error::OutOfRange("1900-02-29 00:00".to_owned())),
+            parse("1900-02-29 00:00")
+        );
+    }
+
+    #[test]
+    fn test_invalid_format() {
+        assert_eq!(Err(Error::Format("".to_owned())), parse(""));
+        assert_eq!(
+            Err(Error::Format("2019-09-16".to_owned())),
+            parse("2019-09-16")
+        );
+        assert_eq!(
+            Err(Error::Format("2019-09-16 12h34".to_owned())),
+            parse("2019-09-16 12h34")
+        );
+    }
+
+    #[test]
+    fn test_rfc2822() {
+        assert_eq!(
+            Ok(1568637240),
+            parse_rfc2822("Mon, 16 Sep 2019 12:34:00 -0000")
+        );
+        assert_eq!(Ok(1568637240), parse_rfc2822("16 Sep 2019 12:34:00 GMT"));
+        assert_eq!(
+            Ok(1568637240),
+            parse_rfc2822("Mon, 16 Sep 2019 09:34:00 -0300")
+        );
+    }
+
+    #[test]
+    fn test_rfc2822_invalid() {
+        assert_eq!(
+            Err(Error::Format("Mon, 16 Foo 2019 12:34:00 -0000".to_owned())),
+            parse_rfc2822("Mon, 16 Foo 2019 12:34:00 -0000")
+        );
+        assert_eq!(
+            Err(Error::Format("Mon, 16 Sep 2019 12:34:00".to_owned())),
+            parse_rfc2822("Mon, 16 Sep 2019 12:34:00")
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!("1970-01-01 00:00", format(0));
+        assert_eq!("2000-02-29 00:00", format(951782400));
+        assert_eq!("1969-12-31 23:59", format(-60));
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        assert_eq!(
+            Err(Error::OutOfRange("2019-13-01 00:00".to_owned())),
+            parse("2019-13-01 00:00")
+        );
+        assert_eq!(
+            Err(Error::OutOfRange("2019-09-16 24:00".to_owned())),
+            parse("2019-09-16 24:00")
+        );
+        assert_eq!(Ok(253402300740), parse("9999-12-31 23:59"));
+        assert_eq!(
+            Err(Error::OutOfRange("10000-01-01 00:00".to_owned())),
+            parse("10000-01-01 00:00")
+        );
+        // would overflow when converted to seconds
+        let huge = "9223372036854775807-01-01 00:00";
+        assert_eq!(Err(Error::OutOfRange(huge.to_owned())), parse(huge));
+        assert!(parse_rfc2822("16 Sep 9223372036854775807 12:34:00 GMT").is_err());
+    }
+}
@@ -0,0 +1,460 @@
+use kuchiki::traits::*;
+use kuchiki::NodeRef;
+use serde_derive::Serialize;
+use std::fmt;
+use url::Url;
+
+use super::{
+    date_parser, entry_id, find_table, has_no_results, href, is_search_page, page_title,
+    parse_integer, select, select_all, select_category, select_parent_href, select_text,
+    select_timestamp, select_u32, size_parser, try_attr, SelectorProfile,
+};
+
+/// Result of running the selectors used by parse_html() against a page.
+///
+/// The Display implementation formats the report as plain text, suitable for
+/// bug reports.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    /// Url the page was fetched from
+    pub url: String,
+    /// Title of the page
+    pub title: String,
+    /// Whether the page looks like a search page
+    pub is_search_page: bool,
+    /// Whether the page has the message shown when a search has no matches
+    pub has_no_results: bool,
+    /// Number of rows found in the table of entries
+    pub rows: usize,
+    /// Selectors that were checked
+    pub checks: Vec<SelectorCheck>,
+    /// Fields whose values seem to have moved to another column
+    pub shifted: Vec<ShiftedColumn>,
+}
+
+/// How many times a selector matched
+#[derive(Debug, Serialize)]
+pub struct SelectorCheck {
+    /// Name of the field extracted with the selector
+    pub field: &'static str,
+    /// CSS selector
+    pub selector: String,
+    /// Whether the selector is applied to each row of the table, instead of
+    /// the whole page
+    pub per_row: bool,
+    /// Whether the field is allowed to be missing (eg comments, pagination)
+    pub optional: bool,
+    /// Number of rows where the selector matched and its value could be
+    /// parsed, or 1 if it matched on the page for page selectors
+    pub matched: usize,
+    /// Number of rows checked, or 1 for page selectors
+    pub total: usize,
+}
+
+impl SelectorCheck {
+    /// Returns whether the selector matched everywhere it was applied
+    pub fn is_ok(&self) -> bool {
+        self.matched == self.total
+    }
+
+    /// Returns whether the selector failing means the page can't be parsed
+    pub fn is_failure(&self) -> bool {
+        !self.optional && !self.is_ok()
+    }
+}
+
+/// A field whose column didn't have the expected value, while other columns did
+#[derive(Debug, Serialize)]
+pub struct ShiftedColumn {
+    /// Name of the field
+    pub field: &'static str,
+    /// Column the field is expected in, starting at 1
+    pub expected: usize,
+    /// Columns where a value of the same kind was found on every row
+    pub found: Vec<usize>,
+}
+
+impl Report {
+    /// Returns whether every required selector matched on every row, and no
+    /// column seems shifted
+    pub fn is_healthy(&self) -> bool {
+        !self.checks.iter().any(SelectorCheck::is_failure) && self.shifted.is_empty()
+    }
+}
+
+/// Kind of value expected in a column, used to find where a value moved
+#[derive(Clone, Copy)]
+enum CellKind {
+    Integer,
+    Size,
+    Date,
+}
+
+impl CellKind {
+    fn matches(self, cell: &NodeRef) -> bool {
+        let text = cell.text_contents();
+        let text = text.trim();
+        match self {
+            CellKind::Integer => !text.is_empty() && text.parse::<u32>().is_ok(),
+            CellKind::Size => size_parser::parse(text).is_ok(),
+            CellKind::Date => try_attr(cell, "data-timestamp")
+                .ok()
+                .and_then(|t| t)
+                .map_or_else(
+                    || date_parser::parse(text).is_ok(),
+                    |t| t.parse::<i64>().is_ok(),
+                ),
+        }
+    }
+}
+
+/// A field of a row, with the kind of its value if it can be recognized by
+/// the value alone
+struct RowField {
+    name: &'static str,
+    selector: fn(&SelectorProfile) -> &str,
+    optional: bool,
+    kind: Option<CellKind>,
+    check: fn(&NodeRef, &str, &Url) -> bool,
+}
+
+const ROW_FIELDS: &[RowField] = &[
+    RowField {
+        name: "category",
+        selector: |p| &p.category,
+        optional: false,
+        kind: None,
+        check: |row, sel, url| select_category(row, sel, url).is_ok(),
+    },
+    RowField {
+        name: "name",
+        selector: |p| &p.name,
+        optional: false,
+        kind: None,
+        check: |row, sel, url| {
+            select(row, sel)
+                .and_then(|a| href(&a, url))
+                .and_then(|u| entry_id(sel, &u))
+                .is_ok()
+        },
+    },
+    RowField {
+        name: "comments",
+        selector: |p| &p.comments,
+        optional: true,
+        kind: None,
+        check: |row, sel, _| {
+            select_text(row, sel)
+                .and_then(|t| parse_integer::<u32>(sel, t.trim().to_owned()))
+                .is_ok()
+        },
+    },
+    RowField {
+        name: "torrent",
+        selector: |p| &p.torrent,
+        optional: false,
+        kind: None,
+        check: |row, sel, url| select_parent_href(row, sel, url).is_ok(),
+    },
+    RowField {
+        name: "magnet",
+        selector: |p| &p.magnet,
+        optional: false,
+        kind: None,
+        check: |row, sel, url| select_parent_href(row, sel, url).is_ok(),
+    },
+    RowField {
+        name: "size",
+        selector: |p| &p.size,
+        optional: false,
+        kind: Some(CellKind::Size),
+        check: |row, sel, _| {
+            select_text(row, sel)
+                .map(|t| size_parser::parse(t.trim()).is_ok())
+                .unwrap_or(false)
+        },
+    },
+    RowField {
+        name: "date",
+        selector: |p| &p.date,
+        optional: false,
+        kind: Some(CellKind::Date),
+        check: |row, sel, _| select_timestamp(row, sel).is_ok(),
+    },
+    RowField {
+        name: "seeders",
+        selector: |p| &p.seeders,
+        optional: false,
+        kind: Some(CellKind::Integer),
+        check: |row, sel, _| select_u32(row, sel).is_ok(),
+    },
+    RowField {
+        name: "leechers",
+        selector: |p| &p.leechers,
+        optional: false,
+        kind: Some(CellKind::Integer),
+        check: |row, sel, _| select_u32(row, sel).is_ok(),
+    },
+    RowField {
+        name: "downloads",
+        selector: |p| &p.downloads,
+        optional: false,
+        kind: Some(CellKind::Integer),
+        check: |row, sel, _| select_u32(row, sel).is_ok(),
+    },
+];
+
+/// Runs every selector used by parse_html() against a page, to find out
+/// which parts of the page layout changed.
+///
+/// The url is only used to resolve links, and defaults to https://nyaa.si/
+/// if it's invalid.
+pub fn diagnose(html: &str, url: &str) -> Report {
+    diagnose_with(&SelectorProfile::default(), html, url)
+}
+
+/// Same as diagnose(), but checks the selectors of a profile instead of the
+/// default ones.
+pub fn diagnose_with(profile: &SelectorProfile, html: &str, url: &str) -> Report {
+    let current_url = Url::parse(url)
+        .or_else(|_| Url::parse("https://nyaa.si/"))
+        .expect("Default url is valid");
+    let dom = kuchiki::parse_html().one(html);
+    let table = find_table(&dom, profile);
+    let rows = match table {
+        Some(ref table) => select_all(table, &profile.row)
+            .map(|rows| rows.map(|r| r.as_node().clone()).collect())
+            .unwrap_or_default(),
+        None => Vec::new(),
+    };
+
+    let page_check = |field, selector: &str, optional| SelectorCheck {
+        field: field,
+        selector: selector.to_owned(),
+        per_row: false,
+        optional: optional,
+        matched: if dom.select_first(selector).is_ok() {
+            1
+        } else {
+            0
+        },
+        total: 1,
+    };
+    let mut checks = vec![SelectorCheck {
+        field: "table",
+        selector: profile.table.clone(),
+        per_row: false,
+        optional: has_no_results(&dom),
+        matched: if table.is_some() { 1 } else { 0 },
+        total: 1,
+    }];
+    let mut shifted = Vec::new();
+
+    for field in ROW_FIELDS {
+        let selector = (field.selector)(profile);
+        let matched = rows
+            .iter()
+            .filter(|row| (field.check)(row, selector, &current_url))
+            .count();
+        if matched < rows.len() {
+            if let (Some(kind), Some(expected)) = (field.kind, column(selector)) {
+                let found = shifted_columns(&rows, expected, kind);
+                if !found.is_empty() {
+                    shifted.push(ShiftedColumn {
+                        field: field.name,
+                        expected: expected,
+                        found: found,
+                    });
+                }
+            }
+        }
+        checks.push(SelectorCheck {
+            field: field.name,
+            selector: selector.to_owned(),
+            per_row: true,
+            optional: field.optional,
+            matched: matched,
+            total: rows.len(),
+        });
+    }
+
+    checks.push(page_check("current page", &profile.current_page, true));
+    checks.push(page_check("pages", &profile.pages, true));
+    checks.push(page_check("next page", &profile.next_page, true));
+    checks.push(page_check("previous page", &profile.previous_page, true));
+    checks.push(page_check("result counts", &profile.page_info, true));
+
+    Report {
+        url: url.to_owned(),
+        title: page_title(&dom),
+        is_search_page: is_search_page(&dom, profile),
+        has_no_results: has_no_results(&dom),
+        rows: rows.len(),
+        checks: checks,
+        shifted: shifted,
+    }
+}
+
+/// Returns the column a cell selector points to, from its last `:nth-child(n)`
+fn column(selector: &str) -> Option<usize> {
+    let start = selector.rfind(":nth-child(")? + ":nth-child(".len();
+    let end = start + selector[start..].find(')')?;
+    selector[start..end].trim().parse().ok()
+}
+
+/// Returns the columns, other than the expected one, holding a value of the
+/// given kind on every row
+fn shifted_columns(rows: &[NodeRef], expected: usize, kind: CellKind) -> Vec<usize> {
+    let cells = rows
+        .iter()
+        .map(|row| {
+            row.children()
+                .elements()
+                .filter(|e| &*e.name.local == "td")
+                .map(|e| e.as_node().clone())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let columns = cells.iter().map(Vec::len).max().unwrap_or(0);
+    (1..=columns)
+        .filter(|&column| column != expected)
+        .filter(|&column| {
+            cells
+                .iter()
+                .all(|row| row.get(column - 1).map_or(false, |c| kind.matches(c)))
+        })
+        .collect()
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let yes_no = |b| if b { "yes" } else { "no" };
+
+        writeln!(f, "Url:         {}", self.url)?;
+        writeln!(f, "Title:       {}", self.title)?;
+        writeln!(f, "Search page: {}", yes_no(self.is_search_page))?;
+        writeln!(f, "No results:  {}", yes_no(self.has_no_results))?;
+        writeln!(f, "Rows:        {}", self.rows)?;
+        writeln!(f)?;
+        writeln!(f, "Selectors:")?;
+        for check in self.checks.iter() {
+            let status = match (check.is_ok(), check.matched, check.optional) {
+                (true, _, _) => "ok",
+                (false, 0, true) => "absent (optional)",
+                (false, _, true) => "partial (optional)",
+                (false, 0, false) => "MISSING",
+                (false, _, false) => "PARTIAL",
+            };
+            let matched = if check.per_row {
+                format!("{}/{} rows", check.matched, check.total)
+            } else {
+                format!("{}/{}", check.matched, check.total)
+            };
+            writeln!(
+                f,
+                "  {:<14} {:<18} {:<10} {}",
+                check.field, status, matched, check.selector
+            )?;
+        }
+        if !self.shifted.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "Shifted columns:")?;
+            for shift in self.shifted.iter() {
+                writeln!(
+                    f,
+                    "  {:<14} expected in column {}, found in column(s) {}",
+                    shift.field,
+                    shift.expected,
+                    shift
+                        .found
+                        .iter()
+                        .map(|c| c.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )?;
+            }
+        }
+        writeln!(f)?;
+        write!(
+            f,
+            "Status:      {}",
+            if self.is_healthy() {
+                "ok"
+            } else {
+                "layout changed"
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROW: &str = r#"<tr class="default">
+        <td><a href="/?c=1_2" title="Anime - English-translated"></a></td>
+        <td colspan="2"><a href="/view/1" title="Name">Name</a></td>
+        <td class="text-center">
+            <a href="/download/1.torrent"><i class="fa fa-fw fa-download"></i></a>
+            <a href="magnet:?xt=urn:btih:0123456789abcdef0123456789abcdef01234567"><i class="fa fa-fw fa-magnet"></i></a>
+        </td>
+        <td class="text-center">1.4 GiB</td>
+        <td class="text-center" data-timestamp="1568637240">2019-09-16 12:34</td>
+        {counts}
+    </tr>"#;
+
+    fn page(counts: &str) -> String {
+        format!(
+            r#"<html><head><title>Browse :: Nyaa</title></head><body>
+            <input name="q">
+            <div class="table-responsive"><table><tbody>{}{}</tbody></table></div>
+            </body></html>"#,
+            ROW.replace("{counts}", counts),
+            ROW.replace("{counts}", counts)
+        )
+    }
+
+    #[test]
+    fn test_healthy() {
+        let html = page(
+            r#"<td class="text-center">1</td><td class="text-center">2</td><td class="text-center">3</td>"#,
+        );
+        let report = diagnose(&html, "https://nyaa.si/");
+        assert_eq!(report.rows, 2);
+        assert!(report.is_healthy(), "{}", report);
+    }
+
+    #[test]
+    fn test_shifted_column() {
+        // a new column was added before the seeders
+        let html = page(
+            r#"<td class="text-center">new</td><td class="text-center">1</td><td class="text-center">2</td><td class="text-center">3</td>"#,
+        );
+        let report = diagnose(&html, "https://nyaa.si/");
+        assert!(!report.is_healthy());
+
+        let seeders = report.checks.iter().find(|c| c.field == "seeders").unwrap();
+        assert_eq!((seeders.matched, seeders.total), (0, 2));
+        assert_eq!(report.shifted.len(), 1);
+        assert_eq!(report.shifted[0].field, "seeders");
+        assert_eq!(report.shifted[0].found, vec![7, 8, 9]);
+    }
+
+    #[test]
+    fn test_column() {
+        assert_eq!(column("td.text-center:nth-child(6)"), Some(6));
+        assert_eq!(column("td:nth-child(2) > a:not(.comments)"), Some(2));
+        assert_eq!(column("td.seeders"), None);
+    }
+
+    #[test]
+    fn test_missing_table() {
+        let report = diagnose(
+            "<html><title>Just a moment...</title></html>",
+            "https://nyaa.si/",
+        );
+        assert!(!report.is_search_page);
+        assert_eq!(report.rows, 0);
+        assert!(report.checks[0].is_failure());
+    }
+}
@@ -0,0 +1,189 @@
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+
+/// Error returned when a page can't be parsed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum ParseError {
+    /// The url of the page is invalid
+    BadPageUrl { url: String, reason: String },
+    /// The page isn't a search page (for example, a captcha, maintenance or 404 page)
+    NotASearchPage { title: String },
+    /// The page is a search page, but the table of entries couldn't be found
+    MissingTable,
+    /// The RSS feed isn't valid XML
+    BadXml { reason: String },
+    /// A CSS selector is invalid
+    InvalidSelector { selector: String },
+    /// No element matched a selector. `row` is the index of the table row
+    /// being parsed, if any.
+    MissingSelector {
+        selector: String,
+        row: Option<usize>,
+    },
+    /// The element matched by a selector has no parent
+    MissingParent {
+        selector: String,
+        row: Option<usize>,
+    },
+    /// An element doesn't have an attribute. `path` lists the element and its
+    /// ancestors, starting from the document root.
+    MissingAttribute {
+        attribute: String,
+        path: Vec<String>,
+    },
+    /// A node that should be an element isn't
+    NotAnElement { path: Vec<String> },
+    /// The text of an element isn't a valid integer
+    BadInteger {
+        selector: String,
+        row: Option<usize>,
+        value: String,
+        reason: String,
+    },
+    /// An element has neither a valid timestamp attribute nor a valid date as text
+    BadDate {
+        selector: String,
+        row: Option<usize>,
+        value: String,
+    },
+    /// The category code (eg "1_2") of an entry isn't valid
+    BadCategory {
+        selector: String,
+        row: Option<usize>,
+        value: String,
+    },
+    /// An url found on the page couldn't be joined with the page url
+    BadUrlJoin { href: String, reason: String },
+    /// A page number in the pagination isn't valid
+    BadPageNumber { value: String },
+    /// A labelled field on an entry's page couldn't be found
+    MissingField { label: String },
+    /// The type of an entry couldn't be determined from its classes
+    MissingEntryKind { classes: String },
+}
+
+impl ParseError {
+    /// Attaches the index of the table row being parsed to this error, if it
+    /// can hold one.
+    pub(crate) fn at_row(self, index: usize) -> Self {
+        use ParseError::*;
+
+        match self {
+            MissingSelector { selector, .. } => MissingSelector {
+                selector: selector,
+                row: Some(index),
+            },
+            MissingParent { selector, .. } => MissingParent {
+                selector: selector,
+                row: Some(index),
+            },
+            BadInteger {
+                selector,
+                value,
+                reason,
+                ..
+            } => BadInteger {
+                selector: selector,
+                row: Some(index),
+                value: value,
+                reason: reason,
+            },
+            BadDate {
+                selector, value, ..
+            } => BadDate {
+                selector: selector,
+                row: Some(index),
+                value: value,
+            },
+            BadCategory {
+                selector, value, ..
+            } => BadCategory {
+                selector: selector,
+                row: Some(index),
+                value: value,
+            },
+            other => other,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ParseError::*;
+
+        match self {
+            BadPageUrl { url, reason } => write!(f, "Unable to parse url {}: {}", url, reason),
+            NotASearchPage { title } => write!(f, "Not a search page: '{}'", title),
+            MissingTable => write!(f, "Unable to find table of entries"),
+            BadXml { reason } => write!(f, "Unable to parse feed: {}", reason),
+            InvalidSelector { selector } => write!(f, "Invalid selector {}", selector),
+            MissingSelector { selector, row } => {
+                write!(f, "Unable to find element with {}", selector)?;
+                write_row(f, *row)
+            }
+            MissingParent { selector, row } => {
+                write!(f, "Unable to find parent of {}", selector)?;
+                write_row(f, *row)
+            }
+            MissingAttribute { attribute, path } => write!(
+                f,
+                "Unable to find attribute {} in {}",
+                attribute,
+                path.join("/")
+            ),
+            NotAnElement { path } => write!(f, "Unable to convert {} to element", path.join("/")),
+            BadInteger {
+                selector,
+                row,
+                value,
+                reason,
+            } => {
+                write!(
+                    f,
+                    "Unable to parse {} ({}) to an integer: {}",
+                    selector, value, reason
+                )?;
+                write_row(f, *row)
+            }
+            BadDate {
+                selector,
+                row,
+                value,
+            } => {
+                write!(f, "Unable to parse {} ({}) to a date", selector, value)?;
+                write_row(f, *row)
+            }
+            BadCategory {
+                selector,
+                row,
+                value,
+            } => {
+                write!(f, "Unable to parse {} ({}) to a category", selector, value)?;
+                write_row(f, *row)
+            }
+            BadUrlJoin { href, reason } => {
+                write!(
+                    f,
+                    "Unable to join href url {} with page url: {}",
+                    href, reason
+                )
+            }
+            BadPageNumber { value } => write!(f, "Unable to parse page number '{}'", value),
+            MissingField { label } => write!(f, "Unable to find field {}", label),
+            MissingEntryKind { classes } => {
+                write!(f, "Unable to find entry type in classes {}", classes)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[inline]
+fn write_row(f: &mut fmt::Formatter, row: Option<usize>) -> fmt::Result {
+    match row {
+        Some(row) => write!(f, " (row {})", row),
+        None => Ok(()),
+    }
+}
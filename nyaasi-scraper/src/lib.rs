@@ -1,18 +1,34 @@
 #[macro_use]
 extern crate lazy_static;
 
+#[cfg(feature = "client")]
+pub mod client;
+pub mod date_parser;
+pub mod doctor;
+mod error;
 pub mod magnet_uri;
+mod profile;
+pub mod retry;
+pub mod rss;
+pub mod search;
 pub mod size_parser;
+pub mod transport;
+pub mod view;
 
 use kuchiki::traits::*;
 use kuchiki::{ElementData, NodeData, NodeDataRef, NodeRef};
-use serde_derive::Serialize;
+use serde_derive::{Deserialize, Serialize};
 use url::Url;
 
 use magnet_uri::MagnetURI;
 
+pub use error::ParseError;
+pub use profile::SelectorProfile;
+pub use rss::{parse_rss, parse_rss_with_options};
+pub use view::{parse_view_html, Comment, FileList, FileNode, NyaasiTorrentDetails, UserRole};
+
 /// Type of an entry
-#[derive(Debug, Serialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub enum EntryKind {
     /// This entry was deleted
     Deleted,
@@ -42,19 +58,41 @@ impl EntryKind {
 }
 
 /// Download links for an entry
-#[derive(Debug, Serialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "RawLinks")]
 pub struct Links {
     /// Link to download the torrent file
     pub torrent: String,
     /// Raw magnet uri
     pub magnet: String,
-    /// Parsed magnet uri
-    #[serde(skip_serializing)]
+    /// Parsed magnet uri, None if the link isn't a magnet link. Fields that
+    /// can't be parsed are kept as unknown fields.
     pub parsed_magnet: Option<MagnetURI>,
 }
 
+/// Links as they are deserialized. The magnet uri is parsed again if the
+/// parsed uri is missing, as in results serialized by older versions.
+#[derive(Deserialize)]
+struct RawLinks {
+    torrent: String,
+    magnet: String,
+    #[serde(default)]
+    parsed_magnet: Option<MagnetURI>,
+}
+
+impl From<RawLinks> for Links {
+    fn from(raw: RawLinks) -> Links {
+        let magnet = raw.magnet;
+        Links {
+            parsed_magnet: raw.parsed_magnet.or_else(|| parse_magnet(&magnet)),
+            torrent: raw.torrent,
+            magnet: magnet,
+        }
+    }
+}
+
 /// Information about the size of an entry
-#[derive(Debug, Serialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Sizes {
     /// Raw, human readable string representing the size
     pub raw: String,
@@ -67,10 +105,16 @@ pub struct Sizes {
 }
 
 /// Represents a download entry
-#[derive(Debug, Serialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct NyaasiEntry {
-    /// URL of this entry (parsing this page isn't supported for now)
+    /// URL of this entry. The html on this url can be provided to
+    /// parse_view_html() to scrape it
     pub url: String,
+    /// Numeric id of this entry, taken from its url (`/view/<id>`)
+    pub id: u64,
+    /// Category code of this entry, as (category, subcategory). For example,
+    /// `(1, 2)` is "Anime - English-translated" on nyaa.si
+    pub category: (u8, u8),
     /// Type of this entry
     pub kind: EntryKind,
     /// Name of the entry
@@ -83,6 +127,8 @@ pub struct NyaasiEntry {
     pub sizes: Sizes,
     /// Date added
     pub date: String,
+    /// Date added, as an unix timestamp in seconds
+    pub timestamp: i64,
     /// Number of seeders
     pub seeders: u32,
     /// Number of leechers
@@ -92,7 +138,7 @@ pub struct NyaasiEntry {
 }
 
 /// Data about a page of the search query
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Page {
     /// Url of the page. The html on this url can be provided to
     /// parse_html() to scrape it
@@ -102,21 +148,60 @@ pub struct Page {
 }
 
 /// Pagination data from a page
-#[derive(Debug, Serialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Pagination {
     /// List of pages around the current
     pub pages: Vec<Page>,
     /// Current page
     pub current: Page,
+    /// Page after the current, or None if the current page is the last
+    pub next: Option<Page>,
+    /// Page before the current, or None if the current page is the first
+    pub previous: Option<Page>,
+    /// Number of the last page. Computed from the total number of results when
+    /// the page shows it, otherwise this is the highest page linked from the
+    /// pager, which only shows the pages around the current one.
+    pub last_page: u32,
+    /// Total number of results of the search, if shown on the page. The site
+    /// caps this number, so it might be lower than the real total.
+    pub total_results: Option<u32>,
+    /// Position of the first entry of the current page among all results,
+    /// starting at 1
+    pub first_index: Option<u32>,
+    /// Position of the last entry of the current page among all results
+    pub last_index: Option<u32>,
 }
 
 /// Data contained in a nyaa.si page.
-#[derive(Debug, Serialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Results {
     /// Entries in the page, in chronological order (aka newest last)
     pub entries: Vec<NyaasiEntry>,
     /// Pagination information extracted from the page.
     pub pagination: Option<Pagination>,
+    /// Rows that couldn't be parsed. Only filled when parsing in lenient mode,
+    /// otherwise the first of those errors is returned instead.
+    pub warnings: Vec<Warning>,
+}
+
+/// A row of the table that couldn't be parsed, and was skipped
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Warning {
+    /// Index of the row in the table, in the order it appears in the page
+    /// (aka newest first)
+    pub row: usize,
+    /// Error that happened while parsing the row
+    pub error: ParseError,
+}
+
+/// Options for parse_html_with_options()
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// If true, rows that can't be parsed are skipped and reported in
+    /// Results::warnings instead of failing the whole page
+    pub lenient: bool,
+    /// Selectors used to find each part of the page
+    pub selectors: SelectorProfile,
 }
 
 impl Results {
@@ -129,13 +214,38 @@ impl Results {
     ///
     /// assert_eq!(r.entries.len(), 0);
     /// assert!(r.pagination.is_none());
+    /// assert_eq!(r.warnings.len(), 0);
     /// ```
     pub fn empty() -> Results {
         Results {
             entries: Vec::new(),
             pagination: None,
+            warnings: Vec::new(),
         }
     }
+
+    /// Merges the results of the page after this one into this result set.
+    ///
+    /// Entries stay in chronological order, so the older entries of the next
+    /// page are placed before the current ones. The pagination is replaced
+    /// with the one of the next page, so it can be followed further. Warnings
+    /// are appended, and their row index stays relative to their own page.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut r = nyaasi_scraper::Results::empty();
+    /// r.merge(nyaasi_scraper::Results::empty());
+    ///
+    /// assert_eq!(r.entries.len(), 0);
+    /// ```
+    pub fn merge(&mut self, next: Results) {
+        let mut entries = next.entries;
+        entries.append(&mut self.entries);
+        self.entries = entries;
+        self.pagination = next.pagination;
+        self.warnings.extend(next.warnings);
+    }
 }
 
 /// Parses HTML source and the page's url into a more usable format.
@@ -146,155 +256,399 @@ impl Results {
 /// The same url used for fetching the html string should be passed as the second
 /// parameter for this function.
 ///
-/// An error is returned if parsing fails.
-pub fn parse_html(html: &str, url: &str) -> Result<Results, String> {
-    let current_url = Url::parse(url).map_err(|e| format!("Unable to parse url {}: {}", url, e))?;
+/// If the search has no matches, an empty result set is returned. An error is
+/// returned if parsing fails, or if the page isn't a search page (for example,
+/// a captcha, maintenance or 404 page).
+pub fn parse_html(html: &str, url: &str) -> Result<Results, ParseError> {
+    parse_html_with_options(html, url, &ParseOptions::default())
+}
+
+/// Same as parse_html(), but allows customizing how the page is parsed.
+///
+/// In lenient mode, rows that can't be parsed are skipped and reported in
+/// Results::warnings, instead of making the whole page fail to parse.
+///
+/// Each part of the page is found with the selectors of the options, which
+/// allows parsing sites running modified versions of the nyaa codebase.
+pub fn parse_html_with_options(
+    html: &str,
+    url: &str,
+    options: &ParseOptions,
+) -> Result<Results, ParseError> {
+    let profile = &options.selectors;
+    let current_url = parse_url(url)?;
     let dom = kuchiki::parse_html().one(html);
 
-    let table = dom
-        .select_first("div.table-responsive > table > tbody > tr > td.text-center > a > i.fa-magnet")
-        .map_err(|()| "Unable to find first table row".to_owned())
-        .and_then(|e| {
-            e.as_node()
-                .parent()
-                .and_then(|e| e.parent())
-                .and_then(|e| e.parent())
-                .and_then(|e| e.parent())
-                .and_then(|e| e.parent())
-                .ok_or_else(|| "Unable to find table from first row".to_owned())
-        })?;
-
-    let mut entries = table
-        .select("tbody > tr")
-        .map_err(|()| "Unable to find table rows from table".to_owned())?
-        .map(|row| {
-            use std::str::FromStr;
-
-            let raw_magnet = select_parent_href(
-                row.as_node(),
-                "td.text-center:nth-child(3) > a > i.fa-magnet",
-                &current_url,
-            )?;
-            let magnet = MagnetURI::from_str(&raw_magnet).ok();
-            let magnet_size = (&magnet).as_ref().and_then(|m| m.length());
-            let raw_size = select_text(row.as_node(), "td.text-center:nth-child(4)")?;
-
-            Ok(NyaasiEntry {
-                url: href(&select(row.as_node(), "td:nth-child(2) > a:not(.comments)")?, &current_url)?,
-                kind: EntryKind::from_class_name(&attr(row.as_node(), "class")?),
-                name: select_text(row.as_node(), "td:nth-child(2) > a:not(.comments)")?,
-                comments: select_text(row.as_node(), "td:nth-child(1) > a.comments > i")
-                    .unwrap_or_else(|_| "0".to_owned())
-                    .parse::<u32>()
-                    .map_err(|e| format!("Unable to parse comment count as u32: {}", e))?,
-                links: Links {
-                    torrent: select_parent_href(
-                        row.as_node(),
-                        "td.text-center:nth-child(3) > a > i.fa-download",
-                        &current_url,
-                    )?,
-                    magnet: raw_magnet,
-                    parsed_magnet: magnet,
-                },
-                sizes: Sizes {
-                    raw: raw_size.clone(),
-                    parsed_from_magnet: magnet_size,
-                    parsed_from_raw: size_parser::parse(&raw_size).ok(),
-                },
-                date: select_text(row.as_node(), "td.text-center:nth-child(5)")?,
-                seeders: select_u32(row.as_node(), "td.text-center:nth-child(6)")?,
-                leechers: select_u32(row.as_node(), "td.text-center:nth-child(7)")?,
-                downloads: select_u32(row.as_node(), "td.text-center:nth-child(8)")?,
-            })
-        })
-        .collect::<Result<Vec<_>, String>>()?;
-
-    let pagination = match dom.select_first("ul.pagination > li.active > a") {
-        Err(_) => None,
-        Ok(current_page_node) => Some(make_page(&current_page_node, &current_url).and_then(
-            |current| {
-                Ok(Pagination {
-                    pages: dom
-                        .select("ul.pagination > li:not(.disabled):not(.next) > a:not([rel])")
-                        .map_err(|()| "Unable to find page list".to_owned())?
-                        .map(|e| make_page(&e, &current_url))
-                        .collect::<Result<Vec<_>, String>>()?,
-                    current: current,
-                })
-            },
-        )?),
+    let table = match find_table(&dom, profile) {
+        Some(table) => table,
+        None => {
+            if !is_search_page(&dom, profile) {
+                return Err(ParseError::NotASearchPage { title: page_title(&dom) });
+            }
+            if has_no_results(&dom) {
+                return Ok(Results::empty());
+            }
+            return Err(ParseError::MissingTable);
+        }
     };
 
+    let mut entries = Vec::new();
+    let mut warnings = Vec::new();
+    for (index, row) in select_all(&table, &profile.row)?.enumerate() {
+        match parse_row(row.as_node(), &current_url, profile) {
+            Ok(entry) => entries.push(entry),
+            Err(e) if options.lenient => warnings.push(Warning {
+                row: index,
+                error: e.at_row(index),
+            }),
+            Err(e) => return Err(e.at_row(index)),
+        }
+    }
+
+    let pagination = parse_pagination(&dom, &current_url, profile)?;
+
     //give newest last
     entries.reverse();
 
     Ok(Results {
         entries: entries,
         pagination: pagination,
+        warnings: warnings,
     })
 }
 
+/// Same as parse_html(), but finds each part of the page with the selectors
+/// of a profile instead of the default ones. This allows parsing sites
+/// running modified versions of the nyaa codebase.
+///
+/// This is a shorthand for parse_html_with_options() with only the selectors set.
+pub fn parse_html_with(
+    profile: &SelectorProfile,
+    html: &str,
+    url: &str,
+) -> Result<Results, ParseError> {
+    let options = ParseOptions {
+        selectors: profile.clone(),
+        ..ParseOptions::default()
+    };
+    parse_html_with_options(html, url, &options)
+}
+
+/// Returns the table of entries of a search page, if there is one
+fn find_table(dom: &NodeRef, profile: &SelectorProfile) -> Option<NodeRef> {
+    dom.select_first(&profile.table)
+        .ok()
+        .map(|e| e.as_node().clone())
+}
+
+fn parse_row(
+    row: &NodeRef,
+    current_url: &Url,
+    profile: &SelectorProfile,
+) -> Result<NyaasiEntry, ParseError> {
+    let raw_magnet = select_parent_href(row, &profile.magnet, current_url)?;
+    let magnet = parse_magnet(&raw_magnet);
+    let magnet_size = (&magnet).as_ref().and_then(|m| m.length());
+    let raw_size = select_text(row, &profile.size)?;
+    let url = href(&select(row, &profile.name)?, current_url)?;
+
+    Ok(NyaasiEntry {
+        id: entry_id(&profile.name, &url)?,
+        category: select_category(row, &profile.category, current_url)?,
+        url: url,
+        kind: EntryKind::from_class_name(&attr(row, "class")?),
+        name: select_text(row, &profile.name)?,
+        comments: match select_text(row, &profile.comments) {
+            Err(_) => 0,
+            Ok(text) => parse_integer(&profile.comments, text.trim().to_owned())?,
+        },
+        links: Links {
+            torrent: select_parent_href(row, &profile.torrent, current_url)?,
+            magnet: raw_magnet,
+            parsed_magnet: magnet,
+        },
+        sizes: Sizes {
+            raw: raw_size.clone(),
+            parsed_from_magnet: magnet_size,
+            parsed_from_raw: size_parser::parse(&raw_size).ok(),
+        },
+        date: select_text(row, &profile.date)?,
+        timestamp: select_timestamp(row, &profile.date)?,
+        seeders: select_u32(row, &profile.seeders)?,
+        leechers: select_u32(row, &profile.leechers)?,
+        downloads: select_u32(row, &profile.downloads)?,
+    })
+}
+
+/// Returns whether a page looks like a search page. Pages that aren't served by
+/// the site itself (captchas, maintenance pages) lack the search bar, and error
+/// pages have the status code at the start of their title.
+fn is_search_page(dom: &NodeRef, profile: &SelectorProfile) -> bool {
+    let title = page_title(dom);
+    let title = title.as_bytes();
+    let is_error = title.len() > 3
+        && title[..3].iter().all(|b| b.is_ascii_digit())
+        && title[3] == b' ';
+    dom.select_first(&profile.search_bar).is_ok() && !is_error
+}
+
+/// Returns whether a page has the message displayed when a search has no matches.
+fn has_no_results(dom: &NodeRef) -> bool {
+    dom.select("h3")
+        .map(|mut headers| headers.any(|h| h.text_contents().trim() == "No results found"))
+        .unwrap_or(false)
+}
+
+fn page_title(dom: &NodeRef) -> String {
+    dom.select_first("title")
+        .map(|t| t.text_contents().trim().to_owned())
+        .unwrap_or_default()
+}
+
+/// Parses the id of an entry from the url of its page (`/view/<id>`).
+/// `sel` describes the link the url was taken from in errors.
+fn entry_id(sel: &str, url: &str) -> Result<u64, ParseError> {
+    let last_segment = Url::parse(url)
+        .ok()
+        .and_then(|u| {
+            u.path_segments()
+                .and_then(|mut s| s.rfind(|s| !s.is_empty()))
+                .map(|s| s.to_owned())
+        })
+        .unwrap_or_default();
+    parse_integer(sel, last_segment)
+}
+
+/// Parses the category code from the `c` parameter of the link matched by a selector.
+fn select_category(node: &NodeRef, sel: &str, current_url: &Url) -> Result<(u8, u8), ParseError> {
+    let url = href(&select(node, sel)?, current_url)?;
+    let code = Url::parse(&url)
+        .ok()
+        .and_then(|u| u.query_pairs().find(|(k, _)| k == "c").map(|(_, v)| v.into_owned()))
+        .unwrap_or_default();
+    parse_category(&code).ok_or_else(|| ParseError::BadCategory {
+        selector: sel.to_owned(),
+        row: None,
+        value: code,
+    })
+}
+
+/// Parses a magnet link, keeping the fields that can't be parsed so one bad
+/// field doesn't lose the whole link
+fn parse_magnet(raw: &str) -> Option<MagnetURI> {
+    MagnetURI::parse_lenient(raw).ok().map(|(magnet, _)| magnet)
+}
+
+/// Parses a category code such as "1_2"
+fn parse_category(code: &str) -> Option<(u8, u8)> {
+    let mut parts = code.split('_');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(category), Some(subcategory), None) => {
+            Some((category.parse().ok()?, subcategory.parse().ok()?))
+        }
+        _ => None,
+    }
+}
+
+fn parse_pagination(
+    dom: &NodeRef,
+    current_url: &Url,
+    profile: &SelectorProfile,
+) -> Result<Option<Pagination>, ParseError> {
+    let current = match dom.select_first(&profile.current_page) {
+        Err(_) => return Ok(None),
+        Ok(current_page_node) => make_page(&current_page_node, current_url)?,
+    };
+    let pages = select_all(dom, &profile.pages)?
+        .map(|e| make_page(&e, current_url))
+        .collect::<Result<Vec<_>, ParseError>>()?;
+    let adjacent = |sel: &str, number: u32| -> Result<Option<Page>, ParseError> {
+        match dom.select_first(sel) {
+            Err(_) => Ok(None),
+            Ok(e) => Ok(Some(Page {
+                url: href(e.as_node(), current_url)?,
+                number: number,
+            })),
+        }
+    };
+    let (total_results, first_index, last_index) = match dom.select_first(&profile.page_info) {
+        Err(_) => (None, None, None),
+        Ok(e) => match parse_page_info(&e.text_contents()) {
+            Some((first, last, total)) => (Some(total), Some(first), Some(last)),
+            None => (None, None, None),
+        },
+    };
+
+    //the pager only links the pages around the current one, so the result
+    //count is preferred when the page shows it
+    let last_page = match (first_index, last_index, total_results) {
+        (Some(first), Some(last), Some(total)) => count_pages(current.number, first, last, total),
+        _ => None,
+    };
+    let last_page = last_page.unwrap_or_else(|| {
+        pages
+            .iter()
+            .map(|p| p.number)
+            .fold(current.number, std::cmp::max)
+    });
+
+    Ok(Some(Pagination {
+        next: adjacent(&profile.next_page, current.number + 1)?,
+        previous: adjacent(&profile.previous_page, current.number.saturating_sub(1))?,
+        last_page: last_page,
+        pages: pages,
+        current: current,
+        total_results: total_results,
+        first_index: first_index,
+        last_index: last_index,
+    }))
+}
+
+/// Parses the "Displaying results 1-75 out of 1000 results." banner into
+/// (first index, last index, total results).
+fn parse_page_info(text: &str) -> Option<(u32, u32, u32)> {
+    let text = text.trim().trim_start_matches("Displaying results").trim_start();
+    let mut parts = text.splitn(2, " out of ");
+    let mut range = parts.next()?.splitn(2, '-');
+    let first = range.next()?.trim().parse().ok()?;
+    let last = range.next()?.trim().parse().ok()?;
+    let total = parts.next()?.split_whitespace().next()?.parse().ok()?;
+    Some((first, last, total))
+}
+
+/// Number of pages needed to show every result, given the number of the
+/// current page and the range of results it shows.
+fn count_pages(current: u32, first: u32, last: u32, total: u32) -> Option<u32> {
+    //every page before the current one is full
+    let per_page = if current > 1 {
+        first.checked_sub(1)? / (current - 1)
+    } else {
+        last.checked_sub(first)? + 1
+    };
+    if per_page == 0 {
+        return None;
+    }
+    let pages = match total {
+        0 => 1,
+        _ => (total - 1) / per_page + 1,
+    };
+    Some(std::cmp::max(current, pages))
+}
+
 #[inline]
-fn make_page(e: &NodeDataRef<ElementData>, current_url: &Url) -> Result<Page, String> {
+fn make_page(e: &NodeDataRef<ElementData>, current_url: &Url) -> Result<Page, ParseError> {
+    let text = e.text_contents();
+    let number = text.split_whitespace().next().unwrap_or("");
     Ok(Page {
         url: href(e.as_node(), current_url)?,
-        number: e
-            .text_contents()
-            .split_whitespace()
-            .next()
-            .ok_or_else(|| "Empty page element content".to_owned())
-            .and_then(|n| {
-                n.parse::<u32>()
-                    .map_err(|e| format!("Unable to parse page number {} to u32: {}", n, e))
-            })?,
+        number: number.parse::<u32>().map_err(|_| ParseError::BadPageNumber {
+            value: number.to_owned(),
+        })?,
+    })
+}
+
+#[inline]
+fn parse_url(url: &str) -> Result<Url, ParseError> {
+    Url::parse(url).map_err(|e| ParseError::BadPageUrl {
+        url: url.to_owned(),
+        reason: e.to_string(),
     })
 }
 
 #[inline]
-fn select(node: &NodeRef, sel: &str) -> Result<NodeRef, String> {
+fn select_all(
+    node: &NodeRef,
+    sel: &str,
+) -> Result<kuchiki::iter::Select<kuchiki::iter::Elements<kuchiki::iter::Descendants>>, ParseError> {
+    node.select(sel).map_err(|()| ParseError::InvalidSelector {
+        selector: sel.to_owned(),
+    })
+}
+
+#[inline]
+fn select(node: &NodeRef, sel: &str) -> Result<NodeRef, ParseError> {
     node.select_first(sel)
-        .map_err(|()| format!("Unable to find element with {}", sel))
+        .map_err(|()| ParseError::MissingSelector {
+            selector: sel.to_owned(),
+            row: None,
+        })
         .map(|n| n.as_node().clone())
 }
 
 #[inline]
-fn select_parent(node: &NodeRef, sel: &str) -> Result<NodeRef, String> {
+fn select_parent(node: &NodeRef, sel: &str) -> Result<NodeRef, ParseError> {
     select(node, sel)?
         .parent()
-        .ok_or_else(|| format!("Unable to find parent of {}", sel))
+        .ok_or_else(|| ParseError::MissingParent {
+            selector: sel.to_owned(),
+            row: None,
+        })
 }
 
 #[inline]
-fn select_text(node: &NodeRef, sel: &str) -> Result<String, String> {
+fn select_text(node: &NodeRef, sel: &str) -> Result<String, ParseError> {
     select(node, sel)
         .map(|e| e.text_contents())
 }
 
 #[inline]
-fn select_u32(node: &NodeRef, sel: &str) -> Result<u32, String> {
-    select_text(node, sel)?
-        .parse::<u32>()
-        .map_err(|e| format!("Unable to parse {} to u32: {}", sel, e))
+fn select_u32(node: &NodeRef, sel: &str) -> Result<u32, ParseError> {
+    parse_integer(sel, select_text(node, sel)?)
+}
+
+#[inline]
+fn parse_integer<T>(sel: &str, text: String) -> Result<T, ParseError>
+where
+    T: std::str::FromStr<Err = std::num::ParseIntError>,
+{
+    text.parse::<T>().map_err(|e| ParseError::BadInteger {
+        selector: sel.to_owned(),
+        row: None,
+        reason: e.to_string(),
+        value: text,
+    })
+}
+
+#[inline]
+fn select_timestamp(node: &NodeRef, sel: &str) -> Result<i64, ParseError> {
+    timestamp(&select(node, sel)?, sel)
 }
 
+/// Reads the `data-timestamp` attribute of an element, falling back to parsing
+/// its text if the attribute is missing. `sel` describes the element in errors.
 #[inline]
-fn select_parent_href(a: &NodeRef, sel: &str, current_url: &Url) -> Result<String, String> {
+fn timestamp(element: &NodeRef, sel: &str) -> Result<i64, ParseError> {
+    match try_attr(element, "data-timestamp")? {
+        Some(timestamp) => parse_integer(sel, timestamp),
+        None => {
+            let text = element.text_contents();
+            date_parser::parse(&text).map_err(|_| ParseError::BadDate {
+                selector: sel.to_owned(),
+                row: None,
+                value: text.trim().to_owned(),
+            })
+        }
+    }
+}
+
+#[inline]
+fn select_parent_href(a: &NodeRef, sel: &str, current_url: &Url) -> Result<String, ParseError> {
     href(&select_parent(a, sel)?, current_url)
 }
 
 #[inline]
-fn attr(a: &NodeRef, attr: &str) -> Result<String, String> {
+fn attr(a: &NodeRef, attr: &str) -> Result<String, ParseError> {
     try_attr(a, attr)?
-        .ok_or_else(|| format!("Unable to find attribute {} in {}",
-                attr,
-                path_to(a).join("/")
-        ))
+        .ok_or_else(|| ParseError::MissingAttribute {
+            attribute: attr.to_owned(),
+            path: path_to(a),
+        })
 }
 
 #[inline]
-fn try_attr(a: &NodeRef, attr: &str) -> Result<Option<String>, String> {
+fn try_attr(a: &NodeRef, attr: &str) -> Result<Option<String>, ParseError> {
     a.as_element()
-        .ok_or_else(|| format!("Unable to convert {} to element", path_to(a).join("/")))
+        .ok_or_else(|| ParseError::NotAnElement { path: path_to(a) })
         .map(|e| e
             .attributes
             .borrow()
@@ -304,14 +658,17 @@ fn try_attr(a: &NodeRef, attr: &str) -> Result<Option<String>, String> {
 }
 
 #[inline]
-fn href(a: &NodeRef, current_url: &Url) -> Result<String, String> {
+fn href(a: &NodeRef, current_url: &Url) -> Result<String, ParseError> {
     try_attr(a, "href")
         .and_then(|option| {
             match option {
                 Some(url) => current_url
                     .join(&url)
                     .map(|u| u.into_string())
-                    .map_err(|e| format!("Unable to join href url {} with page url: {}", url, e)),
+                    .map_err(|e| ParseError::BadUrlJoin {
+                        reason: e.to_string(),
+                        href: url,
+                    }),
                 None => Ok(current_url.as_str().to_owned())
             }
         })
@@ -335,3 +692,33 @@ fn path_to(node: &NodeRef) -> Vec<String> {
     vec.reverse();
     vec
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_info() {
+        assert_eq!(
+            parse_page_info("Displaying results 1-75 out of 1000 results.\nPlease refine your search results if you can't find what you were looking for."),
+            Some((1, 75, 1000))
+        );
+        assert_eq!(
+            parse_page_info("Displaying results 976-1000 out of 1000 results."),
+            Some((976, 1000, 1000))
+        );
+        assert_eq!(parse_page_info("Something else"), None);
+    }
+
+    #[test]
+    fn test_count_pages() {
+        assert_eq!(count_pages(1, 1, 75, 1000), Some(14));
+        assert_eq!(count_pages(5, 301, 375, 1000), Some(14));
+        assert_eq!(count_pages(14, 976, 1000, 1000), Some(14));
+        assert_eq!(count_pages(1, 1, 20, 20), Some(1));
+        assert_eq!(count_pages(2, 76, 150, 150), Some(2));
+        // banners that don't match the page number
+        assert_eq!(count_pages(3, 1, 75, 1000), None);
+        assert_eq!(count_pages(1, 75, 1, 1000), None);
+    }
+}
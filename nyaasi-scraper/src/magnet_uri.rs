@@ -25,14 +25,31 @@
  * SOFTWARE.
  */
 
-extern crate serde_urlencoded;
-
-use serde_urlencoded::de::Error as UrlEncodeError;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::ser::{SerializeStruct, Serializer};
+use serde::Deserializer;
+use serde_derive::{Deserialize, Serialize};
 use std::fmt;
+use std::ops::RangeInclusive;
 use std::str::FromStr;
 
+mod info_hash;
+
+pub use self::info_hash::InfoHash;
+
 const SCHEME: &str = "magnet:?";
 
+/// Characters encoded in field names and values, everything but the
+/// unreserved characters of RFC 3986
+const VALUE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Characters encoded in topics, which keep the colons of their urn
+const URN: &AsciiSet = &VALUE.remove(b':');
+
 pub(self) mod field_name {
     pub const NAME: &str = "dn";
     pub const LENGTH: &str = "xl";
@@ -42,7 +59,11 @@ pub(self) mod field_name {
     pub const KEYWORD: &str = "kt";
     pub const MANIFEST: &str = "mt";
     pub const ADDRESS_TRACKER: &str = "tr";
+    pub const WEB_SEED: &str = "ws";
+    pub const SELECT_ONLY: &str = "so";
     pub const EXTENSION_PREFIX: &str = "x.";
+    /// Extension holding the address of a peer (x.pe)
+    pub const PEER: &str = "pe";
 }
 
 pub(self) mod exact_topic_urn {
@@ -53,17 +74,31 @@ pub(self) mod exact_topic_urn {
     pub const AICH: &str = "urn:aich:";
     pub const KAZAA: &str = "urn:kzhash:";
     pub const BITTORRENT_INFO_HASH: &str = "urn:btih:";
+    pub const BITTORRENT_MULTIHASH: &str = "urn:btmh:";
     pub const MD5: &str = "urn:md5:";
 }
 
 #[derive(Debug)]
 pub enum Error {
     Scheme,
-    UrlEncode(UrlEncodeError),
     Field(String, String),
     ExactTopic(String),
+    InfoHash(String),
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Scheme => write!(f, "Not a magnet link"),
+            Error::Field(key, val) => write!(f, "Invalid value {} for field {}", val, key),
+            Error::ExactTopic(topic) => write!(f, "Invalid topic {}", topic),
+            Error::InfoHash(hash) => write!(f, "Invalid info hash {}", hash),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 impl Error {
     fn with_field(key: &str, val: &str) -> Self {
         Error::Field(key.to_owned(), val.to_owned())
@@ -71,11 +106,40 @@ impl Error {
 }
 
 /// A struct holding fields stored in a Magnet URI
-#[derive(Debug, Default)]
+///
+/// It's serialized as an object with the list of fields, along with the
+/// info hash (in hex), name and trackers for convenience. Only the fields
+/// are read when deserializing.
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct MagnetURI {
     fields: Vec<Field>,
 }
 
+impl serde::Serialize for MagnetURI {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("MagnetURI", 4)?;
+        state.serialize_field("info_hash", &self.info_hash())?;
+        state.serialize_field("name", &self.name())?;
+        state.serialize_field("trackers", &self.trackers())?;
+        state.serialize_field("fields", &self.fields)?;
+        state.end()
+    }
+}
+
+/// MagnetURI as it is deserialized, without the fields repeated for
+/// convenience
+#[derive(Deserialize)]
+struct SerializedMagnetURI {
+    fields: Vec<Field>,
+}
+
+impl<'de> serde::Deserialize<'de> for MagnetURI {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <SerializedMagnetURI as serde::Deserialize>::deserialize(deserializer)
+            .map(|m| MagnetURI { fields: m.fields })
+    }
+}
+
 impl MagnetURI {
     pub fn has_extensions(&self) -> bool {
         self.fields.iter().any(Field::is_extension)
@@ -85,10 +149,18 @@ impl MagnetURI {
         self.fields.iter().any(Field::is_unknown)
     }
 
+    /// Whether two topics with the same index conflict. Topics with
+    /// different indices (like xt.1 and xt.2) describe different files.
     pub fn has_topic_conflict(&self) -> bool {
-        self.iter_topics().any(|topic1| {
-            self.iter_topics()
-                .any(|topic2| Topic::conflicts(topic1, topic2))
+        self.fields.iter().any(|field1| {
+            self.fields
+                .iter()
+                .any(|field2| match (field1.topic(), field2.topic()) {
+                    (Some(topic1), Some(topic2)) => {
+                        field1.index() == field2.index() && Topic::conflicts(topic1, topic2)
+                    }
+                    _ => false,
+                })
         })
     }
 
@@ -124,14 +196,130 @@ impl MagnetURI {
         self.iter_topics().collect()
     }
 
-    pub fn info_hashes(&self) -> Vec<&BTInfoHash> {
+    /// Returns the v1 (btih) and v2 (btmh) info hashes, in order
+    pub fn info_hashes(&self) -> Vec<InfoHash> {
         self.iter_field_values(Field::info_hash).collect()
     }
 
-    pub fn info_hash(&self) -> Option<&BTInfoHash> {
+    /// Returns the first info hash, v1 or v2
+    pub fn info_hash(&self) -> Option<InfoHash> {
         self.iter_field_values(Field::info_hash).next()
     }
 
+    /// Returns the tracker urls (tr)
+    pub fn trackers(&self) -> Vec<&str> {
+        self.iter_field_values(Field::tracker).collect()
+    }
+
+    /// Returns the web seed urls (ws)
+    pub fn web_seeds(&self) -> Vec<&str> {
+        self.iter_field_values(Field::web_seed).collect()
+    }
+
+    /// Returns the urls the file can be downloaded from (as)
+    pub fn acceptable_sources(&self) -> Vec<&str> {
+        self.iter_field_values(Field::acceptable_source).collect()
+    }
+
+    /// Returns the urls of P2P sources for the file (xs)
+    pub fn exact_sources(&self) -> Vec<&str> {
+        self.iter_field_values(Field::exact_source).collect()
+    }
+
+    /// Returns the search keywords (kt)
+    pub fn keywords(&self) -> Vec<&str> {
+        self.iter_field_values(Field::keyword).collect()
+    }
+
+    /// Returns the addresses of peers (x.pe), as `host:port`
+    pub fn peers(&self) -> Vec<&str> {
+        self.iter_field_values(Field::peer).collect()
+    }
+
+    /// Returns the indices of the files to download (so), or None if all
+    /// files should be downloaded
+    pub fn select_only(&self) -> Option<&[RangeInclusive<u64>]> {
+        self.iter_field_values(Field::select_only).next()
+    }
+
+    /// Whether the file with the given index should be downloaded
+    pub fn is_selected(&self, file: u64) -> bool {
+        match self.select_only() {
+            None => true,
+            Some(ranges) => ranges.iter().any(|r| r.contains(&file)),
+        }
+    }
+
+    /// Returns the indices of the indexed fields (like xt.1 and tr.1), in
+    /// increasing order. Values of the other methods include indexed fields.
+    pub fn indices(&self) -> Vec<u32> {
+        let mut indices = self
+            .fields
+            .iter()
+            .filter_map(Field::index)
+            .collect::<Vec<_>>();
+        indices.sort();
+        indices.dedup();
+        indices
+    }
+
+    /// Returns the fields with the given index, without their index, or the
+    /// fields without index if None
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nyaasi_scraper::magnet_uri::MagnetURI;
+    ///
+    /// let magnet: MagnetURI = "magnet:?dn.1=first&dn.2=second&tr=udp%3A%2F%2Ft%3A80"
+    ///     .parse()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(magnet.indices(), vec![1, 2]);
+    /// assert_eq!(magnet.group(Some(2)).name(), Some("second"));
+    /// assert_eq!(magnet.group(None).trackers(), vec!["udp://t:80"]);
+    /// ```
+    pub fn group(&self, index: Option<u32>) -> MagnetURI {
+        MagnetURI {
+            fields: self
+                .fields
+                .iter()
+                .filter(|f| f.index() == index)
+                .map(|f| f.unindexed().clone())
+                .collect(),
+        }
+    }
+
+    /// Parses a magnet link, keeping the fields that can't be parsed as
+    /// unknown fields instead of failing. The errors of those fields are
+    /// returned along with the URI. Fails only if the link isn't a magnet
+    /// link.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nyaasi_scraper::magnet_uri::MagnetURI;
+    ///
+    /// let raw = "magnet:?xl=big&dn=name";
+    /// let (magnet, errors) = MagnetURI::parse_lenient(raw).unwrap();
+    ///
+    /// assert_eq!(magnet.name(), Some("name"));
+    /// assert_eq!(errors.len(), 1);
+    /// assert_eq!(magnet.to_string(), raw);
+    /// ```
+    pub fn parse_lenient(s: &str) -> Result<(MagnetURI, Vec<Error>), Error> {
+        let mut errors = Vec::new();
+        let fields = split_query(strip_scheme(s)?)
+            .map(|pair| {
+                pair.parse().unwrap_or_else(|e| {
+                    errors.push(e);
+                    pair.unknown()
+                })
+            })
+            .collect();
+        Ok((MagnetURI { fields: fields }, errors))
+    }
+
     fn iter_field_values<'a, F, T>(&'a self, f: F) -> impl Iterator<Item = T> + 'a
     where
         F: Fn(&'a Field) -> Option<T> + Sized + 'a,
@@ -161,52 +349,177 @@ impl MagnetURI {
         self.add_field(Field::Extension(ext_name.to_owned(), val.to_owned()))
     }
 
+    /// Adds a tracker, unless the URI already has it
+    pub fn add_tracker(&mut self, url: &str) -> &Self {
+        if !self.trackers().contains(&url) {
+            self.add_field(Field::AddressTracker(url.to_owned()));
+        }
+        self
+    }
+
+    /// Removes a tracker
+    pub fn remove_tracker(&mut self, url: &str) -> &Self {
+        self.fields.retain(|f| f.tracker() != Some(url));
+        self
+    }
+
+    /// Removes all trackers
+    pub fn clear_trackers(&mut self) -> &Self {
+        self.fields.retain(|f| f.tracker().is_none());
+        self
+    }
+
+    /// Adds a web seed, unless the URI already has it
+    pub fn add_web_seed(&mut self, url: &str) -> &Self {
+        if !self.web_seeds().contains(&url) {
+            self.add_field(Field::WebSeed(url.to_owned()));
+        }
+        self
+    }
+
+    /// Adds a peer address (`host:port`), unless the URI already has it
+    pub fn add_peer(&mut self, address: &str) -> &Self {
+        if !self.peers().contains(&address) {
+            self.add_extension(field_name::PEER, address);
+        }
+        self
+    }
+
+    /// Sets the indices of the files to download
+    pub fn set_select_only(&mut self, files: Vec<RangeInclusive<u64>>) -> &Self {
+        self.set_unique_field(|f| f.select_only().is_none(), Field::SelectOnly(files))
+    }
+
     pub fn set_name(&mut self, name: &str) -> &Self {
         self.set_unique_field(|f| f.name().is_none(), Field::Name(name.to_owned()))
     }
 
-    pub fn set_info_hash(&mut self, bith: BTInfoHash) -> &Self {
+    /// Sets the info hash, replacing the one with the same version
+    pub fn set_info_hash(&mut self, hash: InfoHash) -> &Self {
         self.set_unique_field(
-            |f| match f {
-                Field::Topic(Topic::BitTorrentInfoHash(_)) => false,
-                _ => true,
-            },
-            Field::Topic(Topic::BitTorrentInfoHash(bith)),
+            |f| f.info_hash().map(|h| h.version()) != Some(hash.version()),
+            Field::Topic(Topic::from(hash)),
         )
     }
 
-    fn set_unique_field<F>(&mut self, retain_filter: F, field: Field) -> &Self
+    /// Replaces the fields without index that don't pass the filter
+    fn set_unique_field<F>(&mut self, mut retain_filter: F, field: Field) -> &Self
     where
         F: FnMut(&Field) -> bool,
     {
-        self.fields.retain(retain_filter);
+        self.fields
+            .retain(|f| f.index().is_some() || retain_filter(f));
         self.add_field(field)
     }
 }
 
+/// Formats the URI as a magnet link, with the fields in order. Parsing the
+/// link gives back the same fields.
+///
+/// # Examples
+///
+/// ```
+/// use nyaasi_scraper::magnet_uri::MagnetURI;
+///
+/// let mut magnet: MagnetURI = "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a"
+///     .parse()
+///     .unwrap();
+/// magnet.add_name("Some show & more");
+///
+/// assert_eq!(
+///     magnet.to_string(),
+///     "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a&dn=Some%20show%20%26%20more"
+/// );
+/// ```
+impl fmt::Display for MagnetURI {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", SCHEME)?;
+        for (i, field) in self.fields.iter().enumerate() {
+            if i > 0 {
+                write!(f, "&")?;
+            }
+            write!(f, "{}", field)?;
+        }
+        Ok(())
+    }
+}
+
 impl FromStr for MagnetURI {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if !s.starts_with(SCHEME) {
-            return Err(Error::Scheme);
+        Ok(MagnetURI {
+            fields: split_query(strip_scheme(s)?)
+                .map(|pair| pair.parse())
+                .collect::<Result<Vec<_>, _>>()?,
+        })
+    }
+}
+
+fn strip_scheme(s: &str) -> Result<&str, Error> {
+    match_prefix(s, SCHEME).ok_or(Error::Scheme)
+}
+
+/// A `key=value` pair of the query of a magnet link
+struct QueryPair<'a> {
+    raw_key: &'a str,
+    raw_val: &'a str,
+}
+
+impl<'a> QueryPair<'a> {
+    /// Parses the field, with its index if the key has one (like xt.1)
+    fn parse(&self) -> Result<Field, Error> {
+        let key = decode(self.raw_key);
+        let val = decode(self.raw_val);
+        let (name, index) = split_index(&key);
+        match (Field::from_str(name, &val)?, index) {
+            (Field::Unknown(_, _), _) => Ok(self.unknown()),
+            (field, None) => Ok(field),
+            (field, Some(index)) => Ok(Field::Indexed(index, Box::new(field))),
         }
-        let (_, qs) = s.split_at(SCHEME.len());
-        let parse_result: Result<Vec<(String, String)>, _> = serde_urlencoded::from_str(qs);
-        match parse_result {
-            Err(e) => Err(Error::UrlEncode(e)),
-            Ok(parts) => Ok(MagnetURI {
-                fields: parts
-                    .iter()
-                    .map(|(k, v)| Field::from_str(k, v))
-                    .collect::<Result<Vec<_>, _>>()?,
-            }),
+    }
+
+    /// Keeps the pair as it is, as an unknown field
+    fn unknown(&self) -> Field {
+        Field::Unknown(self.raw_key.to_owned(), self.raw_val.to_owned())
+    }
+}
+
+/// Splits the query of a magnet link into `key=value` pairs, without
+/// decoding them. Keys without value have an empty value.
+fn split_query(qs: &str) -> impl Iterator<Item = QueryPair<'_>> {
+    qs.split('&').filter(|pair| !pair.is_empty()).map(|pair| {
+        let mut parts = pair.splitn(2, '=');
+        QueryPair {
+            raw_key: parts.next().unwrap_or(""),
+            raw_val: parts.next().unwrap_or(""),
         }
+    })
+}
+
+/// Decodes a key or value of a query, where spaces may be encoded as "+"
+fn decode(s: &str) -> String {
+    percent_decode_str(&s.replace('+', " "))
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+/// Splits the index from the key of an indexed field, like xt.1
+fn split_index(key: &str) -> (&str, Option<u32>) {
+    if key.starts_with(field_name::EXTENSION_PREFIX) {
+        return (key, None);
+    }
+    match key.rfind('.') {
+        Some(dot) => match u32::from_str(&key[dot + 1..]) {
+            Ok(index) => (&key[..dot], Some(index)),
+            Err(_) => (key, None),
+        },
+        None => (key, None),
     }
 }
 
 /// Field of a Magnet URI
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Field {
     Name(String),
     Length(u64),
@@ -216,7 +529,15 @@ pub enum Field {
     Keyword(String),
     Manifest(String),
     AddressTracker(String),
+    WebSeed(String),
+    /// Indices of the files to download, starting at 0
+    SelectOnly(Vec<RangeInclusive<u64>>),
     Extension(String, String),
+    /// Field with an index in its key, like xt.1. Fields with the same index
+    /// describe the same file.
+    Indexed(u32, Box<Field>),
+    /// Field with an unknown key, with its key and value kept as they were
+    /// written in the URI, percent-encoding included
     Unknown(String, String),
 }
 
@@ -237,6 +558,11 @@ impl Field {
             KEYWORD => Ok(Keyword(val.to_owned())),
             MANIFEST => Ok(Manifest(val.to_owned())),
             ADDRESS_TRACKER => Ok(AddressTracker(val.to_owned())),
+            WEB_SEED => Ok(WebSeed(val.to_owned())),
+            SELECT_ONLY => match parse_ranges(val) {
+                None => Err(Error::with_field(key, val)),
+                Some(ranges) => Ok(SelectOnly(ranges)),
+            },
             _ => {
                 if key.starts_with(EXTENSION_PREFIX) {
                     let (_, ext_name) = key.split_at(EXTENSION_PREFIX.len());
@@ -248,47 +574,179 @@ impl Field {
         }
     }
 
-    fn is_extension(&self) -> bool {
+    /// Returns the field without its index
+    pub fn unindexed(&self) -> &Field {
         match self {
+            Field::Indexed(_, field) => field.unindexed(),
+            field => field,
+        }
+    }
+
+    /// Returns the index of the field, if it has one
+    pub fn index(&self) -> Option<u32> {
+        match self {
+            Field::Indexed(index, _) => Some(*index),
+            _ => None,
+        }
+    }
+
+    fn is_extension(&self) -> bool {
+        match self.unindexed() {
             Field::Extension(_, _) => true,
             _ => false,
         }
     }
 
     fn is_unknown(&self) -> bool {
-        match self {
+        match self.unindexed() {
             Field::Unknown(_, _) => true,
             _ => false,
         }
     }
 
     fn name(&self) -> Option<&str> {
-        match self {
+        match self.unindexed() {
             Field::Name(ref name) => Some(name),
             _ => None,
         }
     }
 
     fn length(&self) -> Option<u64> {
-        match self {
+        match self.unindexed() {
             Field::Length(len) => Some(*len),
             _ => None,
         }
     }
 
     fn topic(&self) -> Option<&Topic> {
-        match self {
+        match self.unindexed() {
             Field::Topic(topic) => Some(topic),
             _ => None,
         }
     }
 
-    fn info_hash(&self) -> Option<&BTInfoHash> {
-        match self {
-            Field::Topic(Topic::BitTorrentInfoHash(ref hash)) => Some(hash),
+    fn info_hash(&self) -> Option<InfoHash> {
+        self.topic().and_then(Topic::info_hash)
+    }
+
+    fn tracker(&self) -> Option<&str> {
+        match self.unindexed() {
+            Field::AddressTracker(url) => Some(url),
             _ => None,
         }
     }
+
+    fn web_seed(&self) -> Option<&str> {
+        match self.unindexed() {
+            Field::WebSeed(url) => Some(url),
+            _ => None,
+        }
+    }
+
+    fn acceptable_source(&self) -> Option<&str> {
+        match self.unindexed() {
+            Field::AcceptableSource(url) => Some(url),
+            _ => None,
+        }
+    }
+
+    fn exact_source(&self) -> Option<&str> {
+        match self.unindexed() {
+            Field::ExactSource(url) => Some(url),
+            _ => None,
+        }
+    }
+
+    fn keyword(&self) -> Option<&str> {
+        match self.unindexed() {
+            Field::Keyword(keyword) => Some(keyword),
+            _ => None,
+        }
+    }
+
+    fn peer(&self) -> Option<&str> {
+        match self.unindexed() {
+            Field::Extension(name, address) if name == field_name::PEER => Some(address),
+            _ => None,
+        }
+    }
+
+    fn select_only(&self) -> Option<&[RangeInclusive<u64>]> {
+        match self.unindexed() {
+            Field::SelectOnly(ranges) => Some(ranges),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a list of indices and ranges of indices, like "0,2,4-6"
+fn parse_ranges(s: &str) -> Option<Vec<RangeInclusive<u64>>> {
+    s.split(',')
+        .map(|part| {
+            let mut bounds = part.splitn(2, '-').map(|n| u64::from_str(n.trim()).ok());
+            let start = bounds.next()??;
+            match bounds.next() {
+                None => Some(start..=start),
+                Some(Some(end)) if start <= end => Some(start..=end),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Formats a list of ranges of indices, the inverse of parse_ranges()
+fn format_ranges(ranges: &[RangeInclusive<u64>]) -> String {
+    ranges
+        .iter()
+        .map(|r| {
+            if r.start() == r.end() {
+                r.start().to_string()
+            } else {
+                format!("{}-{}", r.start(), r.end())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+impl Field {
+    /// Returns the key and value of the field, percent-encoded
+    fn encoded_pair(&self) -> (String, String) {
+        use field_name::*;
+        use Field::*;
+
+        let encode = |s: &str| utf8_percent_encode(s, VALUE).to_string();
+        match self {
+            Name(val) => (NAME.to_owned(), encode(val)),
+            Length(len) => (LENGTH.to_owned(), len.to_string()),
+            Topic(topic) => (
+                TOPIC.to_owned(),
+                utf8_percent_encode(&topic.to_string(), URN).to_string(),
+            ),
+            AcceptableSource(val) => (ACCEPTABLE_SOURCE.to_owned(), encode(val)),
+            ExactSource(val) => (EXACT_SOURCE.to_owned(), encode(val)),
+            Keyword(val) => (KEYWORD.to_owned(), encode(val)),
+            Manifest(val) => (MANIFEST.to_owned(), encode(val)),
+            AddressTracker(val) => (ADDRESS_TRACKER.to_owned(), encode(val)),
+            WebSeed(val) => (WEB_SEED.to_owned(), encode(val)),
+            SelectOnly(ranges) => (SELECT_ONLY.to_owned(), encode(&format_ranges(ranges))),
+            Extension(name, val) => (format!("{}{}", EXTENSION_PREFIX, encode(name)), encode(val)),
+            Indexed(index, field) => {
+                let (key, val) = field.encoded_pair();
+                (format!("{}.{}", key, index), val)
+            }
+            Unknown(key, val) => (key.clone(), val.clone()),
+        }
+    }
+}
+
+/// Formats the field as a percent-encoded `key=value` pair. Unknown fields
+/// are written as they were parsed.
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (key, val) = self.encoded_pair();
+        write!(f, "{}={}", key, val)
+    }
 }
 
 // TODO: use more specific types
@@ -297,11 +755,10 @@ type SHA1Hash = String;
 type ED2KHash = String;
 type AICHHash = String;
 type KazaaHash = String;
-type BTInfoHash = String;
 type MD5Hash = String;
 
 /// Topic (hash) of a Magnet URI
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Topic {
     /// urn:tree:tiger:TTHHash
     TigerTreeHash(TTHHash),
@@ -315,13 +772,25 @@ pub enum Topic {
     AICH(AICHHash),
     /// urn:kzhash:KazaaHash
     Kazaa(KazaaHash),
-    /// urn:bith:BTInfoHash
-    BitTorrentInfoHash(BTInfoHash),
+    /// urn:btih:InfoHash, the digest of a v1 torrent
+    BitTorrentInfoHash(#[serde(with = "info_hash::v1")] [u8; 20]),
+    /// urn:btmh:InfoHash, the digest (without multihash prefix) of a v2
+    /// torrent
+    BitTorrentMultihash(#[serde(with = "info_hash::v2")] [u8; 32]),
     /// urn:md5:MD5Hash
     MD5(MD5Hash),
 }
 
 impl Topic {
+    /// Returns the info hash of BitTorrent topics
+    pub fn info_hash(&self) -> Option<InfoHash> {
+        match self {
+            Topic::BitTorrentInfoHash(bytes) => Some(InfoHash::V1(*bytes)),
+            Topic::BitTorrentMultihash(bytes) => Some(InfoHash::V2(*bytes)),
+            _ => None,
+        }
+    }
+
     fn conflicts(&self, other: &Topic) -> bool {
         use Topic::*;
 
@@ -333,6 +802,7 @@ impl Topic {
             (AICH(h1), AICH(h2)) => h1 != h2,
             (Kazaa(h1), Kazaa(h2)) => h1 != h2,
             (BitTorrentInfoHash(h1), BitTorrentInfoHash(h2)) => h1 != h2,
+            (BitTorrentMultihash(h1), BitTorrentMultihash(h2)) => h1 != h2,
             (MD5(h1), MD5(h2)) => h1 != h2,
 
             (TigerTreeHash(tth1), BitPrint(_, tth2)) => tth1 != tth2,
@@ -345,6 +815,16 @@ impl Topic {
     }
 }
 
+/// Wraps an info hash in the topic for its version
+impl From<InfoHash> for Topic {
+    fn from(hash: InfoHash) -> Topic {
+        match hash {
+            InfoHash::V1(bytes) => Topic::BitTorrentInfoHash(bytes),
+            InfoHash::V2(bytes) => Topic::BitTorrentMultihash(bytes),
+        }
+    }
+}
+
 impl FromStr for Topic {
     type Err = Error;
 
@@ -371,7 +851,9 @@ impl FromStr for Topic {
         } else if let Some(hash) = match_prefix(s, exact_topic_urn::KAZAA) {
             Ok(Kazaa(hash.to_owned()))
         } else if let Some(hash) = match_prefix(s, exact_topic_urn::BITTORRENT_INFO_HASH) {
-            Ok(BitTorrentInfoHash(hash.to_owned()))
+            InfoHash::from_btih(hash).map(Topic::from)
+        } else if let Some(hash) = match_prefix(s, exact_topic_urn::BITTORRENT_MULTIHASH) {
+            InfoHash::from_btmh(hash).map(Topic::from)
         } else if let Some(hash) = match_prefix(s, exact_topic_urn::MD5) {
             Ok(MD5(hash.to_owned()))
         } else {
@@ -392,9 +874,18 @@ impl fmt::Display for Topic {
             ED2K(hash) => write!(f, "{}{}", exact_topic_urn::ED2K, hash),
             AICH(hash) => write!(f, "{}{}", exact_topic_urn::AICH, hash),
             Kazaa(hash) => write!(f, "{}{}", exact_topic_urn::KAZAA, hash),
-            BitTorrentInfoHash(hash) => {
-                write!(f, "{}{}", exact_topic_urn::BITTORRENT_INFO_HASH, hash)
-            }
+            BitTorrentInfoHash(bytes) => write!(
+                f,
+                "{}{}",
+                exact_topic_urn::BITTORRENT_INFO_HASH,
+                InfoHash::V1(*bytes)
+            ),
+            BitTorrentMultihash(bytes) => write!(
+                f,
+                "{}{}",
+                exact_topic_urn::BITTORRENT_MULTIHASH,
+                InfoHash::V2(*bytes)
+            ),
             MD5(hash) => write!(f, "{}{}", exact_topic_urn::MD5, hash),
         }
     }
@@ -445,6 +936,234 @@ mod tests {
         assert!(!uri.is_strictly_valid());
     }
 
+    #[test]
+    fn test_display_round_trip() {
+        let raw = "magnet:?xt=urn:btih:229e409ab193b8f262595e1cb23680beba1ecafc&dn=Unknown%20entry&tr=http%3A%2F%2Fnyaa.tracker.wf%3A7777%2Fannounce&tr=udp%3A%2F%2Fopen.stealth.si%3A80%2Fannounce";
+        let uri = MagnetURI::from_str(raw).unwrap();
+        assert_eq!(uri.to_string(), raw);
+
+        let mut uri = MagnetURI::from_str("magnet:?xt=urn:bitprint:3I42H3S6NNFQ2MSVX7XZKYAYSCX5QBYJ.LWPNACQDBZRYXW3VHJVCJ64QBZNGHOHHHZWCLNQ&xl=0&dn=a+b%2Bc").unwrap();
+        uri.add_extension("pe", "1.2.3.4:5678");
+        uri.add_field(Field::Unknown("foo".to_owned(), "0-2%2C4".to_owned()));
+        uri.add_field(Field::AddressTracker(
+            "http://t.example/announce?a=1&b=ä".to_owned(),
+        ));
+        let formatted = uri.to_string();
+        assert_eq!(
+            formatted,
+            "magnet:?xt=urn:bitprint:3I42H3S6NNFQ2MSVX7XZKYAYSCX5QBYJ.LWPNACQDBZRYXW3VHJVCJ64QBZNGHOHHHZWCLNQ&xl=0&dn=a%20b%2Bc&x.pe=1.2.3.4%3A5678&foo=0-2%2C4&tr=http%3A%2F%2Ft.example%2Fannounce%3Fa%3D1%26b%3D%C3%A4"
+        );
+        assert_eq!(MagnetURI::from_str(&formatted).unwrap(), uri);
+    }
+
+    #[test]
+    fn test_indexed() {
+        let uri = MagnetURI::from_str("magnet:?xt.1=urn:btih:229e409ab193b8f262595e1cb23680beba1ecafc&dn.1=first&xt.2=urn:sha1:YNCKHTQCWBTRNJIV4WNAE52SJUQCZO5C&dn.2=second&tr=udp%3A%2F%2Ft%3A80&tr.2=udp%3A%2F%2Fu%3A80").unwrap();
+        assert_eq!(uri.indices(), vec![1, 2]);
+        assert_eq!(uri.names(), vec!["first", "second"]);
+        assert_eq!(uri.trackers(), vec!["udp://t:80", "udp://u:80"]);
+        assert!(!uri.has_topic_conflict());
+
+        // topics of different files don't conflict
+        let hashes = "xt.1=urn:btih:229e409ab193b8f262595e1cb23680beba1ecafc&xt.2=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a";
+        let uri2 = MagnetURI::from_str(&format!("magnet:?{}", hashes)).unwrap();
+        assert!(!uri2.has_topic_conflict());
+        let uri2 =
+            MagnetURI::from_str(&format!("magnet:?{}", hashes.replace(".2=", ".1="))).unwrap();
+        assert!(uri2.has_topic_conflict());
+
+        let first = uri.group(Some(1));
+        assert_eq!(first.name(), Some("first"));
+        assert_eq!(first.info_hash().map(|h| h.version()), Some(1));
+        assert!(first.trackers().is_empty());
+        assert_eq!(uri.group(None).trackers(), vec!["udp://t:80"]);
+        assert_eq!(uri.group(Some(2)).trackers(), vec!["udp://u:80"]);
+        assert!(uri.group(Some(3)).fields.is_empty());
+
+        let second = uri.fields.iter().find(|f| f.index() == Some(2)).unwrap();
+        assert_eq!(
+            second.unindexed(),
+            &Field::Topic(Topic::SHA1("YNCKHTQCWBTRNJIV4WNAE52SJUQCZO5C".to_owned()))
+        );
+        assert_eq!(
+            second.to_string(),
+            "xt.2=urn:sha1:YNCKHTQCWBTRNJIV4WNAE52SJUQCZO5C"
+        );
+    }
+
+    #[test]
+    fn test_indexed_keys() {
+        assert_eq!(split_index("xt.1"), ("xt", Some(1)));
+        assert_eq!(split_index("xt"), ("xt", None));
+        assert_eq!(split_index("xt.a"), ("xt.a", None));
+        assert_eq!(split_index("x.foo.1"), ("x.foo.1", None));
+        // unknown keys are kept whole, even with an index
+        let uri = MagnetURI::from_str("magnet:?foo.1=bar").unwrap();
+        assert_eq!(
+            uri.fields,
+            vec![Field::Unknown("foo.1".to_owned(), "bar".to_owned())]
+        );
+        assert!(uri.indices().is_empty());
+    }
+
+    #[test]
+    fn test_lenient() {
+        let raw =
+            "magnet:?xt=urn:btih:229e409ab193b8f262595e1cb23680beba1ecafc&xl=abc&dn=name&so=1-x";
+        assert!(MagnetURI::from_str(raw).is_err());
+
+        let (uri, errors) = MagnetURI::parse_lenient(raw).unwrap();
+        assert_eq!(uri.name(), Some("name"));
+        assert_eq!(uri.length(), None);
+        assert!(uri.info_hash().is_some());
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].to_string(), "Invalid value abc for field xl");
+        assert_eq!(uri.to_string(), raw);
+
+        assert!(MagnetURI::parse_lenient("http://nyaa.si/").is_err());
+    }
+
+    #[test]
+    fn test_unknown_raw() {
+        let raw = "magnet:?foo=a%2bb+c&dn=a+b&bar.1=%7E&baz=&&qux=1=2";
+        let uri = MagnetURI::from_str(raw).unwrap();
+        assert_eq!(uri.name(), Some("a b"));
+        assert_eq!(
+            uri.fields
+                .iter()
+                .filter(|f| f.is_unknown())
+                .cloned()
+                .collect::<Vec<_>>(),
+            vec![
+                Field::Unknown("foo".to_owned(), "a%2bb+c".to_owned()),
+                Field::Unknown("bar.1".to_owned(), "%7E".to_owned()),
+                Field::Unknown("baz".to_owned(), "".to_owned()),
+                Field::Unknown("qux".to_owned(), "1=2".to_owned()),
+            ]
+        );
+        assert_eq!(
+            uri.to_string(),
+            "magnet:?foo=a%2bb+c&dn=a%20b&bar.1=%7E&baz=&qux=1=2"
+        );
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode("a+b%20c%2B"), "a b c+");
+        assert_eq!(decode("%C3%A4%zz"), "ä%zz");
+        assert_eq!(decode("%FF"), "\u{fffd}");
+    }
+
+    #[test]
+    fn test_trackers() {
+        let mut uri = MagnetURI::from_str("magnet:?xt=urn:btih:229e409ab193b8f262595e1cb23680beba1ecafc&tr=http%3A%2F%2Fa%2Fannounce&ws=http%3A%2F%2Fseed%2Ffile&kt=a+b&tr=udp%3A%2F%2Fb%3A80").unwrap();
+        assert_eq!(uri.trackers(), vec!["http://a/announce", "udp://b:80"]);
+        assert_eq!(uri.web_seeds(), vec!["http://seed/file"]);
+        assert_eq!(uri.keywords(), vec!["a b"]);
+        assert!(uri.exact_sources().is_empty());
+
+        uri.remove_tracker("http://a/announce");
+        uri.add_tracker("udp://b:80");
+        uri.add_tracker("udp://c:80");
+        assert_eq!(uri.trackers(), vec!["udp://b:80", "udp://c:80"]);
+        uri.clear_trackers();
+        assert!(uri.trackers().is_empty());
+        assert_eq!(uri.web_seeds().len(), 1);
+    }
+
+    #[test]
+    fn test_peers_and_select_only() {
+        let mut uri =
+            MagnetURI::from_str("magnet:?xt=urn:btih:229e409ab193b8f262595e1cb23680beba1ecafc&x.pe=10.0.0.1%3A6881&so=0,2,4-6").unwrap();
+        assert_eq!(uri.peers(), vec!["10.0.0.1:6881"]);
+        assert_eq!(uri.select_only(), Some(&[0..=0, 2..=2, 4..=6][..]));
+        assert!(uri.is_selected(5));
+        assert!(!uri.is_selected(3));
+
+        uri.add_peer("[::1]:6881");
+        uri.set_select_only(vec![1..=1]);
+        assert_eq!(uri.peers(), vec!["10.0.0.1:6881", "[::1]:6881"]);
+        assert_eq!(uri.select_only(), Some(&[1..=1][..]));
+        assert_eq!(MagnetURI::from_str(&uri.to_string()).unwrap(), uri);
+
+        assert!(MagnetURI::from_str("magnet:?so=4-2").is_err());
+        assert!(MagnetURI::from_str("magnet:?so=1,,2").is_err());
+        assert!(MagnetURI::from_str("magnet:?dn=x").unwrap().is_selected(9));
+    }
+
+    #[test]
+    fn test_info_hashes() {
+        let v1 = InfoHash::from_btih("c12fe1c06bba254a9dc9f519b335aa7c1367a88a").unwrap();
+        let v2 = InfoHash::from_btmh(
+            "1220caf1e1c30e81cb361b9ee167c4aa64228a7fa4fa9f6105232b28ad099f3a302e",
+        )
+        .unwrap();
+        // hybrid torrents have both hashes
+        let mut uri = MagnetURI::from_str("magnet:?xt=urn:btih:YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKEK&xt=urn:btmh:1220caf1e1c30e81cb361b9ee167c4aa64228a7fa4fa9f6105232b28ad099f3a302e").unwrap();
+        assert_eq!(uri.info_hashes(), vec![v1, v2]);
+        assert!(!uri.has_topic_conflict());
+
+        let other = InfoHash::from_bytes(&[0; 20]).unwrap();
+        uri.set_info_hash(other);
+        assert_eq!(uri.info_hashes(), vec![v2, other]);
+        assert_eq!(
+            uri.to_string(),
+            "magnet:?xt=urn:btmh:1220caf1e1c30e81cb361b9ee167c4aa64228a7fa4fa9f6105232b28ad099f3a302e&xt=urn:btih:0000000000000000000000000000000000000000"
+        );
+
+        match MagnetURI::from_str("magnet:?xt=urn:btih:c12fe1c06bba") {
+            Err(Error::InfoHash(hash)) => assert_eq!(hash, "c12fe1c06bba"),
+            other => panic!("Expected invalid hash, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_serde() {
+        let uri = MagnetURI::from_str("magnet:?xt=urn:btih:229e409ab193b8f262595e1cb23680beba1ecafc&dn=Unknown%20entry&tr=http%3A%2F%2Fnyaa.tracker.wf%3A7777%2Fannounce&xt=urn:bitprint:3I42H3S6NNFQ2MSVX7XZKYAYSCX5QBYJ.LWPNACQDBZRYXW3VHJVCJ64QBZNGHOHHHZWCLNQ&so=1-2").unwrap();
+        let json = serde_json::to_value(&uri).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "info_hash": "229e409ab193b8f262595e1cb23680beba1ecafc",
+                "name": "Unknown entry",
+                "trackers": ["http://nyaa.tracker.wf:7777/announce"],
+                "fields": [
+                    {"Topic": {"BitTorrentInfoHash": "229e409ab193b8f262595e1cb23680beba1ecafc"}},
+                    {"Name": "Unknown entry"},
+                    {"AddressTracker": "http://nyaa.tracker.wf:7777/announce"},
+                    {"Topic": {"BitPrint": [
+                        "3I42H3S6NNFQ2MSVX7XZKYAYSCX5QBYJ",
+                        "LWPNACQDBZRYXW3VHJVCJ64QBZNGHOHHHZWCLNQ"
+                    ]}},
+                    {"SelectOnly": [{"start": 1, "end": 2}]}
+                ]
+            })
+        );
+        assert_eq!(serde_json::from_value::<MagnetURI>(json).unwrap(), uri);
+
+        let invalid = serde_json::json!({
+            "fields": [{"Topic": {"BitTorrentInfoHash": "229e"}}]
+        });
+        assert!(serde_json::from_value::<MagnetURI>(invalid).is_err());
+        // the hash must have the version of the topic
+        let v2 = "1220caf1e1c30e81cb361b9ee167c4aa64228a7fa4fa9f6105232b28ad099f3a302e";
+        for (kind, hash) in &[
+            ("BitTorrentInfoHash", v2),
+            (
+                "BitTorrentMultihash",
+                "229e409ab193b8f262595e1cb23680beba1ecafc",
+            ),
+        ] {
+            let mismatched = serde_json::json!({ "fields": [{"Topic": {*kind: hash}}] });
+            assert!(serde_json::from_value::<MagnetURI>(mismatched).is_err());
+        }
+        let valid = serde_json::json!({
+            "fields": [{"Topic": {"BitTorrentMultihash": v2}}]
+        });
+        let uri = serde_json::from_value::<MagnetURI>(valid).unwrap();
+        assert_eq!(uri.to_string(), format!("magnet:?xt=urn:btmh:{}", v2));
+    }
+
     #[test]
     fn test_invalid_no_length() {
         let uri = MagnetURI::from_str("magnet:?xt=urn:ed2k:31D6CFE0D16AE931B73C59D7E0C089C0&dn=zero_len.fil&xt=urn:bitprint:3I42H3S6NNFQ2MSVX7XZKYAYSCX5QBYJ.LWPNACQDBZRYXW3VHJVCJ64QBZNGHOHHHZWCLNQ&xt=urn:md5:D41D8CD98F00B204E9800998ECF8427E").unwrap();
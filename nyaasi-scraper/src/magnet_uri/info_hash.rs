@@ -0,0 +1,289 @@
+use super::Error;
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+const HEX_ALPHABET: &[u8; 16] = b"0123456789abcdef";
+
+/// Multihash prefix of a SHA-256 digest (function code 0x12, 32 bytes),
+/// the only hash function used by v2 torrents
+const SHA256_MULTIHASH: [u8; 2] = [0x12, 0x20];
+
+/// Info hash of a torrent, which identifies it regardless of the site it
+/// was found on.
+///
+/// Hashes are stored as bytes, so hashes in different encodings or cases
+/// compare equal.
+///
+/// # Examples
+///
+/// ```
+/// use nyaasi_scraper::magnet_uri::InfoHash;
+///
+/// let hex: InfoHash = "C12FE1C06BBA254A9DC9F519B335AA7C1367A88A".parse().unwrap();
+/// let base32: InfoHash = "YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKEK".parse().unwrap();
+///
+/// assert_eq!(hex, base32);
+/// assert_eq!(base32.to_hex(), "c12fe1c06bba254a9dc9f519b335aa7c1367a88a");
+/// assert!("c12fe1c06bba".parse::<InfoHash>().is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InfoHash {
+    /// SHA-1 hash of the info dictionary of a v1 torrent. Written in magnet
+    /// links (urn:btih) as 40 hex or 32 base32 characters.
+    V1([u8; 20]),
+    /// SHA-256 hash of the info dictionary of a v2 torrent. Written in
+    /// magnet links (urn:btmh) as a hex encoded multihash.
+    V2([u8; 32]),
+}
+
+impl InfoHash {
+    /// Creates a hash from a digest, 20 bytes for v1 hashes or 32 bytes for
+    /// v2 hashes
+    pub fn from_bytes(bytes: &[u8]) -> Option<InfoHash> {
+        let mut v1 = [0u8; 20];
+        let mut v2 = [0u8; 32];
+        match bytes.len() {
+            20 => {
+                v1.copy_from_slice(bytes);
+                Some(InfoHash::V1(v1))
+            }
+            32 => {
+                v2.copy_from_slice(bytes);
+                Some(InfoHash::V2(v2))
+            }
+            _ => None,
+        }
+    }
+
+    /// Parses a v1 hash, in hex or base32
+    pub fn from_btih(s: &str) -> Result<InfoHash, Error> {
+        let bytes = match s.len() {
+            40 => decode_hex(s),
+            32 => decode_base32(s),
+            _ => None,
+        };
+        bytes
+            .and_then(|b| InfoHash::from_bytes(&b))
+            .ok_or_else(|| Error::InfoHash(s.to_owned()))
+    }
+
+    /// Parses a v2 hash, as a hex encoded SHA-256 multihash
+    pub fn from_btmh(s: &str) -> Result<InfoHash, Error> {
+        match decode_hex(s) {
+            Some(ref bytes) if bytes.len() == 34 && bytes[..2] == SHA256_MULTIHASH => {
+                InfoHash::from_bytes(&bytes[2..]).ok_or_else(|| Error::InfoHash(s.to_owned()))
+            }
+            _ => Err(Error::InfoHash(s.to_owned())),
+        }
+    }
+
+    /// Returns the digest
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            InfoHash::V1(bytes) => bytes,
+            InfoHash::V2(bytes) => bytes,
+        }
+    }
+
+    /// Returns the version of the torrent the hash is for, 1 or 2
+    pub fn version(&self) -> u8 {
+        match self {
+            InfoHash::V1(_) => 1,
+            InfoHash::V2(_) => 2,
+        }
+    }
+
+    /// Returns the bytes written in magnet links, the digest for v1 hashes
+    /// and the multihash for v2 hashes
+    fn magnet_bytes(&self) -> Vec<u8> {
+        match self {
+            InfoHash::V1(bytes) => bytes.to_vec(),
+            InfoHash::V2(bytes) => SHA256_MULTIHASH
+                .iter()
+                .chain(bytes.iter())
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Encodes the hash as lowercase hex, as written in magnet links
+    pub fn to_hex(&self) -> String {
+        let mut out = String::new();
+        for &byte in self.magnet_bytes().iter() {
+            out.push(HEX_ALPHABET[(byte >> 4) as usize] as char);
+            out.push(HEX_ALPHABET[(byte & 0xf) as usize] as char);
+        }
+        out
+    }
+
+    /// Encodes the hash as uppercase base32, without padding. Magnet links
+    /// only use base32 for v1 hashes.
+    pub fn to_base32(&self) -> String {
+        let mut out = String::new();
+        let mut buffer = 0u32;
+        let mut bits = 0;
+        for &byte in self.magnet_bytes().iter() {
+            buffer = (buffer << 8) | byte as u32;
+            bits += 8;
+            while bits >= 5 {
+                bits -= 5;
+                out.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+            }
+        }
+        if bits > 0 {
+            out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+        }
+        out
+    }
+}
+
+/// Parses a v1 hash in hex or base32, or a v2 multihash in hex
+impl FromStr for InfoHash {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        InfoHash::from_btih(s).or_else(|_| InfoHash::from_btmh(s))
+    }
+}
+
+/// Formats the hash as lowercase hex
+impl fmt::Display for InfoHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+/// Serialized as a hex string, like in magnet links
+impl Serialize for InfoHash {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_hex())
+    }
+}
+
+/// Deserialized from any string accepted by from_str()
+impl<'de> Deserialize<'de> for InfoHash {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+/// Serializes the digest of a v1 hash like an InfoHash, for use with
+/// `#[serde(with)]`. Deserializing fails for v2 hashes.
+pub(super) mod v1 {
+    use super::InfoHash;
+    use serde::de::{self, Deserialize, Deserializer};
+    use serde::ser::{Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8; 20], serializer: S) -> Result<S::Ok, S::Error> {
+        InfoHash::V1(*bytes).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 20], D::Error> {
+        match InfoHash::deserialize(deserializer)? {
+            InfoHash::V1(bytes) => Ok(bytes),
+            hash => Err(de::Error::custom(format!("{} is not a v1 info hash", hash))),
+        }
+    }
+}
+
+/// Serializes the digest of a v2 hash like an InfoHash, for use with
+/// `#[serde(with)]`. Deserializing fails for v1 hashes.
+pub(super) mod v2 {
+    use super::InfoHash;
+    use serde::de::{self, Deserialize, Deserializer};
+    use serde::ser::{Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error> {
+        InfoHash::V2(*bytes).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 32], D::Error> {
+        match InfoHash::deserialize(deserializer)? {
+            InfoHash::V2(bytes) => Ok(bytes),
+            hash => Err(de::Error::custom(format!("{} is not a v2 info hash", hash))),
+        }
+    }
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    let pairs = s.as_bytes().chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return None;
+    }
+    pairs
+        .map(|pair| {
+            let high = (pair[0] as char).to_digit(16)?;
+            let low = (pair[1] as char).to_digit(16)?;
+            Some((high * 16 + low) as u8)
+        })
+        .collect()
+}
+
+fn decode_base32(s: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in s.bytes() {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|&a| a == c.to_ascii_uppercase())? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEX: &str = "c12fe1c06bba254a9dc9f519b335aa7c1367a88a";
+    const BASE32: &str = "YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKEK";
+    const BTMH: &str = "1220caf1e1c30e81cb361b9ee167c4aa64228a7fa4fa9f6105232b28ad099f3a302e";
+
+    #[test]
+    fn test_v1() {
+        let hash = InfoHash::from_btih(HEX).unwrap();
+        assert_eq!(hash.version(), 1);
+        assert_eq!(hash.to_hex(), HEX);
+        assert_eq!(hash.to_base32(), BASE32);
+        assert_eq!(InfoHash::from_btih(&HEX.to_uppercase()).unwrap(), hash);
+        assert_eq!(InfoHash::from_btih(&BASE32.to_lowercase()).unwrap(), hash);
+    }
+
+    #[test]
+    fn test_v2() {
+        let hash = InfoHash::from_btmh(BTMH).unwrap();
+        assert_eq!(hash.version(), 2);
+        assert_eq!(hash.as_bytes().len(), 32);
+        assert_eq!(hash.to_string(), BTMH);
+        assert_eq!(BTMH.parse::<InfoHash>().unwrap(), hash);
+        // v2 hashes must be SHA-256 multihashes
+        assert!(InfoHash::from_btmh(&BTMH[4..]).is_err());
+        assert!(InfoHash::from_btmh(&BTMH.replacen("12", "13", 1)).is_err());
+    }
+
+    #[test]
+    fn test_invalid() {
+        for invalid in &[
+            "",
+            "c12fe1c06bba254a9dc9f519b335aa7c1367a88",
+            "g12fe1c06bba254a9dc9f519b335aa7c1367a88a",
+            "YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKE1",
+            "c12fe1c06bba254a9dc9f519b335aa7c1367a88ä",
+        ] {
+            match invalid.parse::<InfoHash>() {
+                Err(Error::InfoHash(ref s)) if s == invalid => {}
+                other => panic!("Expected error for {}, got {:?}", invalid, other),
+            }
+        }
+    }
+}
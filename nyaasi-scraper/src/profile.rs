@@ -0,0 +1,89 @@
+use serde_derive::{Deserialize, Serialize};
+
+/// CSS selectors used to find each part of a search page.
+///
+/// The default profile matches nyaa.si and sukebei.nyaa.si. Sites running
+/// a modified version of the nyaa codebase can be scraped by changing the
+/// selectors that differ, for example by loading a profile from a file.
+/// Missing fields are filled with the default selectors when deserializing.
+///
+/// Row selectors are relative to a row of the table of entries, and the
+/// row selector is relative to the table.
+///
+/// # Examples
+///
+/// ```
+/// use nyaasi_scraper::SelectorProfile;
+///
+/// let profile: SelectorProfile = serde_json::from_str(r#"{
+///     "seeders": "td.text-center:nth-child(7)"
+/// }"#).unwrap();
+///
+/// assert_eq!(profile.seeders, "td.text-center:nth-child(7)");
+/// assert_eq!(profile.leechers, SelectorProfile::default().leechers);
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SelectorProfile {
+    /// Search bar, only present on pages served by the site itself
+    pub search_bar: String,
+    /// Table of entries
+    pub table: String,
+    /// Rows of the table of entries
+    pub row: String,
+    /// Link to the category of an entry
+    pub category: String,
+    /// Link to the page of an entry, with its name as text
+    pub name: String,
+    /// Link to the comments of an entry, with their count as text
+    pub comments: String,
+    /// Icon inside the link to the torrent file
+    pub torrent: String,
+    /// Icon inside the magnet link
+    pub magnet: String,
+    /// Cell with the size of an entry
+    pub size: String,
+    /// Cell with the date an entry was added
+    pub date: String,
+    /// Cell with the number of seeders
+    pub seeders: String,
+    /// Cell with the number of leechers
+    pub leechers: String,
+    /// Cell with the number of completed downloads
+    pub downloads: String,
+    /// Link to the current page
+    pub current_page: String,
+    /// Links to the pages around the current
+    pub pages: String,
+    /// Link to the next page
+    pub next_page: String,
+    /// Link to the previous page
+    pub previous_page: String,
+    /// Banner with the number of results
+    pub page_info: String,
+}
+
+impl Default for SelectorProfile {
+    fn default() -> SelectorProfile {
+        SelectorProfile {
+            search_bar: "input[name=q]".to_owned(),
+            table: "div.table-responsive > table".to_owned(),
+            row: "tbody > tr".to_owned(),
+            category: "td:nth-child(1) > a".to_owned(),
+            name: "td:nth-child(2) > a:not(.comments)".to_owned(),
+            comments: "td:nth-child(2) > a.comments".to_owned(),
+            torrent: "td.text-center:nth-child(3) > a > i.fa-download".to_owned(),
+            magnet: "td.text-center:nth-child(3) > a > i.fa-magnet".to_owned(),
+            size: "td.text-center:nth-child(4)".to_owned(),
+            date: "td.text-center:nth-child(5)".to_owned(),
+            seeders: "td.text-center:nth-child(6)".to_owned(),
+            leechers: "td.text-center:nth-child(7)".to_owned(),
+            downloads: "td.text-center:nth-child(8)".to_owned(),
+            current_page: "ul.pagination > li.active > a".to_owned(),
+            pages: "ul.pagination > li:not(.disabled):not(.next) > a:not([rel])".to_owned(),
+            next_page: "ul.pagination > li:not(.disabled) > a[rel=next]".to_owned(),
+            previous_page: "ul.pagination > li:not(.disabled) > a[rel=prev]".to_owned(),
+            page_info: "div.pagination-page-info".to_owned(),
+        }
+    }
+}
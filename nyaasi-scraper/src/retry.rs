@@ -0,0 +1,359 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use url::Url;
+
+use super::date_parser;
+use super::transport::{Error, Response, Transport};
+
+/// Statuses returned when the site is overloaded or rate limiting, which
+/// are worth retrying later
+const RETRY_STATUSES: &[u16] = &[429, 500, 502, 503, 504];
+
+/// How a RetryTransport retries failed requests
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Number of retries after the first attempt
+    pub retries: u32,
+    /// Delay before the first retry, doubled after each retry
+    pub base_delay: Duration,
+    /// Longest delay between attempts. Responses asking to retry later than
+    /// that (with Retry-After) are returned instead of retried.
+    pub max_delay: Duration,
+    /// Whether delays are randomized between half and all of their value,
+    /// so clients failing together don't retry together
+    pub jitter: bool,
+    /// Maximum number of requests to each host, retries included, or None
+    /// for no limit
+    pub budget: Option<u32>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            retries: 3,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            jitter: true,
+            budget: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before a retry, starting at 0, without jitter
+    fn backoff(&self, retry: u32) -> Duration {
+        2u32.checked_pow(retry)
+            .and_then(|factor| self.base_delay.checked_mul(factor))
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+
+    fn jitter(&self, delay: Duration) -> Duration {
+        if self.jitter {
+            delay / 2 + delay.mul_f64(random_fraction() / 2.0)
+        } else {
+            delay
+        }
+    }
+}
+
+/// Transport that retries requests failing with a network error or a
+/// status meaning the site is overloaded, waiting longer between each
+/// attempt. Retry-After headers sent with 429 and 503 responses are used
+/// instead of the computed delay.
+///
+/// The number of requests to each host is counted over the lifetime of the
+/// transport, so a budget covers all the pages fetched with it. Requests
+/// over budget fail with `Error::BudgetExhausted`, unless the budget runs
+/// out while retrying, in which case the result of the last attempt is
+/// returned.
+///
+/// Waiting between attempts blocks the thread, so only blocking transports
+/// can be wrapped.
+///
+/// # Examples
+///
+/// ```
+/// use nyaasi_scraper::retry::{RetryPolicy, RetryTransport};
+/// use nyaasi_scraper::transport::{Error, MockTransport, Response, Transport};
+///
+/// let mock = MockTransport::new().with("https://nyaa.si/", Response::new(200, ""));
+/// let policy = RetryPolicy { budget: Some(1), ..RetryPolicy::default() };
+/// let transport = RetryTransport::new(mock, policy);
+///
+/// assert!(transport.get("https://nyaa.si/").is_ok());
+/// match transport.get("https://nyaa.si/") {
+///     Err(Error::BudgetExhausted { host, .. }) => assert_eq!(host, "nyaa.si"),
+///     other => panic!("{:?}", other),
+/// }
+/// ```
+#[derive(Debug)]
+pub struct RetryTransport<T> {
+    inner: T,
+    policy: RetryPolicy,
+    requests: Mutex<HashMap<String, u32>>,
+    sleep: fn(Duration),
+}
+
+impl<T: Transport> RetryTransport<T> {
+    /// Wraps a transport
+    pub fn new(inner: T, policy: RetryPolicy) -> RetryTransport<T> {
+        RetryTransport {
+            inner: inner,
+            policy: policy,
+            requests: Mutex::new(HashMap::new()),
+            sleep: thread::sleep,
+        }
+    }
+
+    /// Returns the wrapped transport
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the policy used for retrying
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    /// Counts a request to the host of a url, failing if it's over budget
+    fn spend(&self, url: &str) -> Result<(), Error> {
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_owned()))
+            .unwrap_or_else(|| url.to_owned());
+        let mut requests = self.requests.lock().expect("Poisoned lock");
+        let count = requests.entry(host.clone()).or_insert(0);
+        match self.policy.budget {
+            Some(budget) if *count >= budget => Err(Error::BudgetExhausted {
+                host: host,
+                budget: budget,
+            }),
+            _ => {
+                *count += 1;
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns how long to wait before retrying, or None if the result
+    /// shouldn't be retried
+    fn retry_delay(&self, result: &Result<Response, Error>, retry: u32) -> Option<Duration> {
+        if retry >= self.policy.retries {
+            return None;
+        }
+        match result {
+            Ok(response) if RETRY_STATUSES.contains(&response.status) => {
+                match retry_after(response) {
+                    Some(delay) if delay > self.policy.max_delay => None,
+                    Some(delay) => Some(delay),
+                    None => Some(self.policy.jitter(self.policy.backoff(retry))),
+                }
+            }
+            Err(Error::Request { .. }) => Some(self.policy.jitter(self.policy.backoff(retry))),
+            _ => None,
+        }
+    }
+}
+
+impl<T: Transport> Transport for RetryTransport<T> {
+    fn get(&self, url: &str) -> Result<Response, Error> {
+        self.spend(url)?;
+        let mut retry = 0;
+        loop {
+            let result = self.inner.get(url);
+            let delay = match self.retry_delay(&result, retry) {
+                None => return result,
+                Some(delay) => delay,
+            };
+            //out of budget for a retry, the last attempt is all there is
+            if self.spend(url).is_err() {
+                return result;
+            }
+            (self.sleep)(delay);
+            retry += 1;
+        }
+    }
+}
+
+/// Delay asked by the Retry-After header of a 429 or 503 response, either
+/// in seconds or as a date
+fn retry_after(response: &Response) -> Option<Duration> {
+    if response.status != 429 && response.status != 503 {
+        return None;
+    }
+    let value = response.header_value("Retry-After")?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = date_parser::parse_rfc2822(value).ok()?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    Some(Duration::from_secs((date - now).max(0) as u64))
+}
+
+/// Random number in [0, 1)
+fn random_fraction() -> f64 {
+    // each RandomState is seeded differently, which is enough for jitter
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0),
+    );
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MockTransport;
+    use std::cell::RefCell;
+
+    thread_local! {
+        static SLEPT: RefCell<Vec<Duration>> = RefCell::new(Vec::new());
+    }
+
+    fn record_sleep(delay: Duration) {
+        SLEPT.with(|s| s.borrow_mut().push(delay));
+    }
+
+    fn slept() -> Vec<u64> {
+        SLEPT.with(|s| s.borrow().iter().map(|d| d.as_secs()).collect())
+    }
+
+    fn transport(response: Response, policy: RetryPolicy) -> RetryTransport<MockTransport> {
+        RetryTransport {
+            sleep: record_sleep,
+            ..RetryTransport::new(
+                MockTransport::new().with("https://nyaa.si/", response),
+                policy,
+            )
+        }
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            jitter: false,
+            ..RetryPolicy::default()
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            max_delay: Duration::from_secs(5),
+            ..policy()
+        };
+        let delays = [0, 1, 2, 3, 40]
+            .iter()
+            .map(|&r| policy.backoff(r).as_secs())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, vec![1, 2, 4, 5, 5]);
+    }
+
+    #[test]
+    fn test_jitter() {
+        let policy = RetryPolicy::default();
+        for _ in 0..100 {
+            let delay = policy.jitter(Duration::from_secs(10));
+            assert!(delay >= Duration::from_secs(5) && delay <= Duration::from_secs(10));
+        }
+    }
+
+    #[test]
+    fn test_retry_status() {
+        let transport = transport(Response::new(503, ""), policy());
+        assert_eq!(transport.get("https://nyaa.si/").unwrap().status, 503);
+        assert_eq!(transport.inner().requests().len(), 4);
+        assert_eq!(slept(), vec![1, 2, 4]);
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let response = Response::new(429, "").header("Retry-After", "7");
+        let transport = transport(
+            response,
+            RetryPolicy {
+                retries: 2,
+                ..policy()
+            },
+        );
+        assert_eq!(transport.get("https://nyaa.si/").unwrap().status, 429);
+        assert_eq!(slept(), vec![7, 7]);
+    }
+
+    #[test]
+    fn test_retry_after_too_long() {
+        let response = Response::new(429, "").header("Retry-After", "3600");
+        let transport = transport(response, policy());
+        assert_eq!(transport.get("https://nyaa.si/").unwrap().status, 429);
+        assert_eq!(transport.inner().requests().len(), 1);
+    }
+
+    #[test]
+    fn test_retry_after_date() {
+        let response =
+            Response::new(503, "").header("Retry-After", "Wed, 21 Oct 2015 07:28:00 GMT");
+        assert_eq!(retry_after(&response), Some(Duration::from_secs(0)));
+        let response = Response::new(500, "").header("Retry-After", "10");
+        assert_eq!(retry_after(&response), None);
+    }
+
+    #[test]
+    fn test_not_retried() {
+        let transport = transport(Response::new(404, ""), policy());
+        assert_eq!(transport.get("https://nyaa.si/").unwrap().status, 404);
+        assert!(transport.get("https://nyaa.si/view/1").is_err());
+        assert_eq!(transport.inner().requests().len(), 2);
+        assert!(slept().is_empty());
+    }
+
+    #[test]
+    fn test_budget() {
+        let transport = transport(
+            Response::new(503, ""),
+            RetryPolicy {
+                budget: Some(2),
+                ..policy()
+            },
+        );
+        assert_eq!(transport.get("https://nyaa.si/").unwrap().status, 503);
+        assert_eq!(
+            transport.get("https://nyaa.si/"),
+            Err(Error::BudgetExhausted {
+                host: "nyaa.si".to_owned(),
+                budget: 2
+            })
+        );
+        assert_eq!(transport.inner().requests().len(), 2);
+        // other hosts have their own budget
+        assert!(transport.get("https://sukebei.nyaa.si/").is_err());
+        assert_eq!(transport.inner().requests().len(), 3);
+    }
+
+    #[test]
+    fn test_budget_while_retrying() {
+        let transport = transport(
+            Response::new(503, "Overloaded"),
+            RetryPolicy {
+                budget: Some(3),
+                ..policy()
+            },
+        );
+        // the last response is returned instead of an error
+        let response = transport.get("https://nyaa.si/").unwrap();
+        assert_eq!(response.status, 503);
+        assert_eq!(response.body, "Overloaded");
+        assert_eq!(transport.inner().requests().len(), 3);
+        // no waiting for a retry that won't happen
+        assert_eq!(slept(), vec![1, 2]);
+    }
+}
@@ -0,0 +1,318 @@
+use roxmltree::{Document, Node};
+use url::form_urlencoded;
+
+use super::{
+    date_parser, entry_id, parse_category, parse_integer, parse_magnet, parse_url, size_parser,
+    EntryKind, Links, NyaasiEntry, ParseError, ParseOptions, Results, Sizes, Warning,
+};
+
+/// Announce url of the tracker of nyaa.si, the first tracker of its magnet links
+const NYAA_ANNOUNCE: &str = "http://nyaa.tracker.wf:7777/announce";
+/// Announce url of the tracker of sukebei.nyaa.si
+const SUKEBEI_ANNOUNCE: &str = "http://sukebei.tracker.wf:8888/announce";
+/// Public trackers both sites add to their magnet links after their own
+const PUBLIC_TRACKERS: &[&str] = &[
+    "udp://open.stealth.si:80/announce",
+    "udp://tracker.opentrackr.org:1337/announce",
+    "udp://exodus.desync.com:6969/announce",
+    "udp://tracker.torrent.eu.org:451/announce",
+];
+
+/// Parses the RSS feed of a search (`https://nyaa.si/?page=rss&query=params`)
+/// into the same format returned by parse_html().
+///
+/// Feeds have no pagination, so Results::pagination is always None.
+///
+/// An error is returned if parsing fails.
+pub fn parse_rss(xml: &str) -> Result<Results, ParseError> {
+    parse_rss_with_options(xml, &ParseOptions::default())
+}
+
+/// Same as parse_rss(), but allows customizing how the feed is parsed.
+///
+/// In lenient mode, items that can't be parsed are skipped and reported in
+/// Results::warnings, like rows of parse_html_with_options(). Feeds don't
+/// use the selectors of the options.
+pub fn parse_rss_with_options(xml: &str, options: &ParseOptions) -> Result<Results, ParseError> {
+    let document = Document::parse(xml).map_err(|e| ParseError::BadXml {
+        reason: e.to_string(),
+    })?;
+
+    let channel = document
+        .root_element()
+        .children()
+        .find(|n| is_rss_element(n, "channel"))
+        .ok_or_else(|| ParseError::MissingSelector {
+            selector: "rss > channel".to_owned(),
+            row: None,
+        })?;
+
+    let mut entries = Vec::new();
+    let mut warnings = Vec::new();
+    let items = channel.children().filter(|n| is_rss_element(n, "item"));
+    for (index, item) in items.enumerate() {
+        match parse_item(&item) {
+            Ok(entry) => entries.push(entry),
+            Err(e) if options.lenient => warnings.push(Warning {
+                row: index,
+                error: e.at_row(index),
+            }),
+            Err(e) => return Err(e.at_row(index)),
+        }
+    }
+
+    //give newest last
+    entries.reverse();
+
+    Ok(Results {
+        entries: entries,
+        pagination: None,
+        warnings: warnings,
+    })
+}
+
+fn parse_item(item: &Node) -> Result<NyaasiEntry, ParseError> {
+    let url = text(item, "guid", false)?;
+    let name = text(item, "title", false)?;
+    let info_hash = text(item, "infoHash", true)?;
+    let link = text(item, "link", false)?;
+
+    // feeds requested with the `magnets` parameter link to the magnet instead
+    // of the torrent file
+    let (torrent, raw_magnet) = if link.starts_with("magnet:") {
+        let id = entry_id("guid", &url)?;
+        let torrent = parse_url(&url)?
+            .join(&format!("/download/{}.torrent", id))
+            .map(|u| u.as_str().to_owned())
+            .map_err(|e| ParseError::BadUrlJoin {
+                href: url.clone(),
+                reason: e.to_string(),
+            })?;
+        (torrent, link)
+    } else {
+        (link, build_magnet(&url, &info_hash, &name)?)
+    };
+    let magnet = parse_magnet(&raw_magnet);
+    let magnet_size = magnet.as_ref().and_then(|m| m.length());
+
+    let raw_size = text(item, "size", true)?;
+    let raw_date = text(item, "pubDate", false)?;
+    let timestamp = date_parser::parse_rfc2822(&raw_date).map_err(|_| ParseError::BadDate {
+        selector: "pubDate".to_owned(),
+        row: None,
+        value: raw_date,
+    })?;
+    let raw_category = text(item, "categoryId", true)?;
+
+    Ok(NyaasiEntry {
+        id: entry_id("guid", &url)?,
+        category: parse_category(&raw_category).ok_or_else(|| ParseError::BadCategory {
+            selector: "nyaa:categoryId".to_owned(),
+            row: None,
+            value: raw_category.clone(),
+        })?,
+        url: url,
+        kind: if text(item, "trusted", true)? == "Yes" {
+            EntryKind::Trusted
+        } else if text(item, "remake", true)? == "Yes" {
+            EntryKind::Remake
+        } else {
+            EntryKind::Default
+        },
+        name: name,
+        comments: parse_integer("nyaa:comments", text(item, "comments", true)?)?,
+        links: Links {
+            torrent: torrent,
+            magnet: raw_magnet,
+            parsed_magnet: magnet,
+        },
+        sizes: Sizes {
+            raw: raw_size.clone(),
+            parsed_from_magnet: magnet_size,
+            parsed_from_raw: size_parser::parse(&raw_size).ok(),
+        },
+        date: date_parser::format(timestamp),
+        timestamp: timestamp,
+        seeders: parse_integer("nyaa:seeders", text(item, "seeders", true)?)?,
+        leechers: parse_integer("nyaa:leechers", text(item, "leechers", true)?)?,
+        downloads: parse_integer("nyaa:downloads", text(item, "downloads", true)?)?,
+    })
+}
+
+/// Builds the magnet link the site shows on the page of an entry, with the
+/// same trackers.
+fn build_magnet(url: &str, info_hash: &str, name: &str) -> Result<String, ParseError> {
+    let encode = |s: &str| form_urlencoded::byte_serialize(s.as_bytes()).collect::<String>();
+    let is_sukebei = parse_url(url)?
+        .host_str()
+        .map_or(false, |h| h.starts_with("sukebei."));
+    let announce = if is_sukebei {
+        SUKEBEI_ANNOUNCE
+    } else {
+        NYAA_ANNOUNCE
+    };
+
+    let mut magnet = format!("magnet:?xt=urn:btih:{}&dn={}", info_hash, encode(name));
+    for tracker in Some(announce).iter().chain(PUBLIC_TRACKERS) {
+        magnet.push_str("&tr=");
+        magnet.push_str(&encode(tracker));
+    }
+    Ok(magnet)
+}
+
+/// Returns whether a node is an element from the RSS vocabulary (without a namespace)
+#[inline]
+fn is_rss_element(node: &Node, name: &str) -> bool {
+    node.is_element() && node.tag_name().name() == name && node.tag_name().namespace().is_none()
+}
+
+/// Returns the trimmed text of a child element of an item. If `nyaa` is true,
+/// the element must be namespaced (eg `nyaa:seeders`), otherwise it must be
+/// an RSS element.
+fn text(item: &Node, name: &str, nyaa: bool) -> Result<String, ParseError> {
+    item.children()
+        .find(|n| {
+            n.is_element()
+                && n.tag_name().name() == name
+                && n.tag_name().namespace().is_some() == nyaa
+        })
+        .map(|n| n.text().unwrap_or("").trim().to_owned())
+        .ok_or_else(|| ParseError::MissingSelector {
+            selector: if nyaa {
+                format!("nyaa:{}", name)
+            } else {
+                name.to_owned()
+            },
+            row: None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEED: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<rss xmlns:atom="http://www.w3.org/2005/Atom" xmlns:nyaa="https://nyaa.si/xmlns/nyaa" version="2.0">
+    <channel>
+        <title>Nyaa - Home - Torrent File RSS</title>
+        <link>https://nyaa.si/</link>
+        <item>
+            <title>Some Book Vol. 1</title>
+            <link>https://nyaa.si/download/1183100.torrent</link>
+            <guid isPermaLink="true">https://nyaa.si/view/1183100</guid>
+            <pubDate>Mon, 16 Sep 2019 11:34:00 -0000</pubDate>
+            <nyaa:seeders>7</nyaa:seeders>
+            <nyaa:leechers>0</nyaa:leechers>
+            <nyaa:downloads>31</nyaa:downloads>
+            <nyaa:infoHash>89abcdef0123456789abcdef0123456789abcdef</nyaa:infoHash>
+            <nyaa:categoryId>3_1</nyaa:categoryId>
+            <nyaa:category>Literature - English-translated</nyaa:category>
+            <nyaa:size>52.3 MiB</nyaa:size>
+            <nyaa:comments>1</nyaa:comments>
+            <nyaa:trusted>No</nyaa:trusted>
+            <nyaa:remake>Yes</nyaa:remake>
+            <description><![CDATA[<a href="https://nyaa.si/view/1183100">#1183100 | Some Book Vol. 1</a>]]></description>
+        </item>
+    </channel>
+</rss>"#;
+
+    #[test]
+    fn test_item() {
+        let results = parse_rss(FEED).unwrap();
+        assert_eq!(results.entries.len(), 1);
+        assert!(results.pagination.is_none());
+
+        let entry = &results.entries[0];
+        assert_eq!(entry.id, 1183100);
+        assert_eq!(entry.category, (3, 1));
+        assert_eq!(entry.name, "Some Book Vol. 1");
+        assert_eq!(entry.comments, 1);
+        assert_eq!(
+            entry.links.torrent,
+            "https://nyaa.si/download/1183100.torrent"
+        );
+        assert_eq!(
+            entry
+                .links
+                .parsed_magnet
+                .as_ref()
+                .and_then(|m| m.info_hash()),
+            "89abcdef0123456789abcdef0123456789abcdef".parse().ok()
+        );
+        assert_eq!(
+            entry.links.parsed_magnet.as_ref().unwrap().trackers(),
+            vec![
+                "http://nyaa.tracker.wf:7777/announce",
+                "udp://open.stealth.si:80/announce",
+                "udp://tracker.opentrackr.org:1337/announce",
+                "udp://exodus.desync.com:6969/announce",
+                "udp://tracker.torrent.eu.org:451/announce",
+            ]
+        );
+        assert_eq!(entry.sizes.parsed_from_raw, Some(54840524));
+        assert_eq!(entry.date, "2019-09-16 11:34");
+        assert_eq!(entry.timestamp, 1568633640);
+        assert_eq!((entry.seeders, entry.leechers, entry.downloads), (7, 0, 31));
+        match entry.kind {
+            EntryKind::Remake => {}
+            ref other => panic!("Expected remake, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_missing_element() {
+        let feed = FEED.replace("<nyaa:seeders>7</nyaa:seeders>", "");
+        assert_eq!(
+            parse_rss(&feed).err(),
+            Some(ParseError::MissingSelector {
+                selector: "nyaa:seeders".to_owned(),
+                row: Some(0),
+            })
+        );
+    }
+
+    #[test]
+    fn test_lenient() {
+        let start = FEED.find("<item>").unwrap();
+        let end = FEED.find("</item>").unwrap() + "</item>".len();
+        let bad_item = FEED[start..end].replace("<nyaa:seeders>7</nyaa:seeders>", "");
+        let feed = FEED.replace("</channel>", &format!("{}</channel>", bad_item));
+        assert!(parse_rss(&feed).is_err());
+
+        let options = ParseOptions {
+            lenient: true,
+            ..ParseOptions::default()
+        };
+        let results = parse_rss_with_options(&feed, &options).unwrap();
+        assert_eq!(results.entries.len(), 1);
+        assert_eq!(results.warnings.len(), 1);
+        assert_eq!(results.warnings[0].row, 1);
+        assert_eq!(
+            results.warnings[0].error,
+            ParseError::MissingSelector {
+                selector: "nyaa:seeders".to_owned(),
+                row: Some(1),
+            }
+        );
+    }
+
+    #[test]
+    fn test_sukebei_trackers() {
+        let feed = FEED.replace("https://nyaa.si/", "https://sukebei.nyaa.si/");
+        let results = parse_rss(&feed).unwrap();
+        let magnet = results.entries[0].links.parsed_magnet.as_ref().unwrap();
+        assert_eq!(
+            magnet.trackers()[0],
+            "http://sukebei.tracker.wf:8888/announce"
+        );
+        assert_eq!(magnet.trackers().len(), 5);
+    }
+
+    #[test]
+    fn test_invalid_xml() {
+        match parse_rss("<rss><channel></rss>") {
+            Err(ParseError::BadXml { .. }) => {}
+            other => panic!("Expected BadXml, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,641 @@
+use std::fmt;
+use std::str::FromStr;
+use url::Url;
+
+use super::parse_category;
+
+// Values valid as of https://github.com/nyaadevs/nyaa/blob/5da76351640909cf3a992150e88a4a314212f077/nyaa/search.py
+const NYAASI_CATEGORIES: &[CategoryInfo] = &[
+    CategoryInfo::new("All categories", &[]),
+    CategoryInfo::new(
+        "Anime",
+        &[
+            "Anime Music Video",
+            "English-translated",
+            "Non-English-translated",
+            "Raw",
+        ],
+    ),
+    CategoryInfo::new("Audio", &["Lossless", "Lossy"]),
+    CategoryInfo::new(
+        "Literature",
+        &["English-translated", "Non-English-translated", "Raw"],
+    ),
+    CategoryInfo::new(
+        "Live Action",
+        &[
+            "English-translated",
+            "Idol/Promotional Video",
+            "Non-English-translated",
+            "Raw",
+        ],
+    ),
+    CategoryInfo::new("Pictures", &["Graphics", "Photos"]),
+    CategoryInfo::new("Software", &["Applications", "Games"]),
+];
+
+const SUKEBEI_CATEGORIES: &[CategoryInfo] = &[
+    CategoryInfo::new("All categories", &[]),
+    CategoryInfo::new("Art", &["Anime", "Doujinshi", "Games", "Manga", "Pictures"]),
+    CategoryInfo::new("Real Life", &["Photobooks and Pictures", "Videos"]),
+];
+
+/// Error returned when a search query is invalid
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The category doesn't exist on the source
+    Category(Source, Category),
+    /// The category code isn't in the `category_subcategory` format
+    CategoryCode(String),
+    /// The filter isn't one of the known filters
+    Filter(String),
+    /// The sort key isn't one of the known keys
+    Sort(String),
+    /// The ordering type isn't one of the known types
+    Order(String),
+    /// The page number isn't valid
+    Page(String),
+    /// The url is invalid
+    Url(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Category(source, category) => write!(
+                f,
+                "Category {} doesn't exist on {}",
+                category,
+                source.base_url()
+            ),
+            Error::CategoryCode(code) => write!(f, "Invalid category {}", code),
+            Error::Filter(filter) => write!(f, "Invalid filter {}", filter),
+            Error::Sort(sort) => write!(f, "Invalid sort key {}", sort),
+            Error::Order(order) => write!(f, "Invalid order type {}", order),
+            Error::Page(page) => write!(f, "Invalid page {}", page),
+            Error::Url(url) => write!(f, "Invalid url {}", url),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Site to search
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// https://nyaa.si
+    Nyaasi,
+    /// https://sukebei.nyaa.si
+    Sukebei,
+}
+
+impl Source {
+    /// Url of the site
+    pub fn base_url(&self) -> &'static str {
+        match self {
+            Source::Nyaasi => "https://nyaa.si",
+            Source::Sukebei => "https://sukebei.nyaa.si",
+        }
+    }
+
+    /// Categories available on the site, indexed by their code
+    pub fn categories(&self) -> &'static [CategoryInfo] {
+        match self {
+            Source::Nyaasi => NYAASI_CATEGORIES,
+            Source::Sukebei => SUKEBEI_CATEGORIES,
+        }
+    }
+
+    /// Returns whether a category exists on the site
+    pub fn has_category(&self, category: Category) -> bool {
+        match self.categories().get(category.category as usize) {
+            None => false,
+            Some(info) => category.subcategory as usize <= info.subcategories.len(),
+        }
+    }
+}
+
+/// Name of a category and of its subcategories
+#[derive(Debug)]
+pub struct CategoryInfo {
+    /// Name of the category
+    pub name: &'static str,
+    /// Names of the subcategories. The subcategory with code `n` is at index
+    /// `n - 1`, code 0 means all subcategories.
+    pub subcategories: &'static [&'static str],
+}
+
+impl CategoryInfo {
+    const fn new(name: &'static str, subcategories: &'static [&'static str]) -> CategoryInfo {
+        CategoryInfo {
+            name: name,
+            subcategories: subcategories,
+        }
+    }
+}
+
+/// Category code of a search or entry, such as `1_2`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Category {
+    /// Category code, 0 means all categories
+    pub category: u8,
+    /// Subcategory code, 0 means all subcategories
+    pub subcategory: u8,
+}
+
+impl Category {
+    /// All categories (`0_0`)
+    pub const ALL: Category = Category {
+        category: 0,
+        subcategory: 0,
+    };
+
+    /// Creates a category from its codes
+    pub fn new(category: u8, subcategory: u8) -> Category {
+        Category {
+            category: category,
+            subcategory: subcategory,
+        }
+    }
+
+    /// Returns the name of this category on a source, such as
+    /// "Anime - English-translated", or None if it doesn't exist there.
+    pub fn name(&self, source: Source) -> Option<String> {
+        if !source.has_category(*self) {
+            return None;
+        }
+        let info = &source.categories()[self.category as usize];
+        Some(match self.subcategory {
+            0 => info.name.to_owned(),
+            sub => format!("{} - {}", info.name, info.subcategories[sub as usize - 1]),
+        })
+    }
+}
+
+impl From<(u8, u8)> for Category {
+    fn from((category, subcategory): (u8, u8)) -> Category {
+        Category::new(category, subcategory)
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}_{}", self.category, self.subcategory)
+    }
+}
+
+/// Filter applied to a search
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    /// Include all entries
+    NoFilter,
+    /// Exclude remakes
+    NoRemakes,
+    /// Include only trusted entries
+    TrustedOnly,
+    /// Include only complete entries
+    Complete,
+}
+
+impl Filter {
+    /// All filters, indexed by their code
+    pub const ALL: [Filter; 4] = [
+        Filter::NoFilter,
+        Filter::NoRemakes,
+        Filter::TrustedOnly,
+        Filter::Complete,
+    ];
+
+    /// Code of the filter, used in the `f` parameter
+    pub fn code(&self) -> u8 {
+        match self {
+            Filter::NoFilter => 0,
+            Filter::NoRemakes => 1,
+            Filter::TrustedOnly => 2,
+            Filter::Complete => 3,
+        }
+    }
+
+    /// Returns the filter with the given code
+    pub fn from_code(code: u8) -> Option<Filter> {
+        Filter::ALL.get(code as usize).cloned()
+    }
+
+    /// Human readable name of the filter
+    pub fn name(&self) -> &'static str {
+        match self {
+            Filter::NoFilter => "No filter",
+            Filter::NoRemakes => "No remakes",
+            Filter::TrustedOnly => "Trusted only",
+            Filter::Complete => "Complete",
+        }
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Filter {
+        Filter::NoFilter
+    }
+}
+
+/// Key used to sort search results
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sort {
+    /// Sort by upload order
+    Id,
+    /// Sort by size
+    Size,
+    /// Sort by number of comments
+    Comments,
+    /// Sort by number of seeders
+    Seeders,
+    /// Sort by number of leechers
+    Leechers,
+    /// Sort by number of completed downloads
+    Downloads,
+}
+
+impl Sort {
+    /// All sort keys
+    pub const ALL: [Sort; 6] = [
+        Sort::Id,
+        Sort::Size,
+        Sort::Comments,
+        Sort::Seeders,
+        Sort::Leechers,
+        Sort::Downloads,
+    ];
+
+    /// Value of the `s` parameter for this key
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Sort::Id => "id",
+            Sort::Size => "size",
+            Sort::Comments => "comments",
+            Sort::Seeders => "seeders",
+            Sort::Leechers => "leechers",
+            Sort::Downloads => "downloads",
+        }
+    }
+}
+
+impl FromStr for Sort {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Sort, Error> {
+        Sort::ALL
+            .iter()
+            .find(|k| k.as_str() == s)
+            .cloned()
+            .ok_or_else(|| Error::Sort(s.to_owned()))
+    }
+}
+
+/// Ordering of sorted search results
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    /// Ascending order
+    Asc,
+    /// Descending order
+    Desc,
+}
+
+impl Order {
+    /// All ordering types
+    pub const ALL: [Order; 2] = [Order::Asc, Order::Desc];
+
+    /// Value of the `o` parameter for this ordering
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Order::Asc => "asc",
+            Order::Desc => "desc",
+        }
+    }
+}
+
+impl FromStr for Order {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Order, Error> {
+        Order::ALL
+            .iter()
+            .find(|o| o.as_str() == s)
+            .cloned()
+            .ok_or_else(|| Error::Order(s.to_owned()))
+    }
+}
+
+/// A search on nyaa.si or sukebei, which can be turned into the url of its
+/// results page.
+///
+/// # Examples
+///
+/// ```
+/// use nyaasi_scraper::search::{Category, Filter, SearchQuery, Source};
+///
+/// let url = SearchQuery::new(Source::Nyaasi)
+///     .filter(Filter::TrustedOnly)
+///     .category(Category::new(1, 2))
+///     .query("some show")
+///     .to_url()
+///     .unwrap();
+///
+/// assert_eq!(url.as_str(), "https://nyaa.si/?f=2&c=1_2&p=1&q=some+show");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchQuery {
+    /// Site to search
+    pub source: Source,
+    /// Url to use instead of the source's url, for mirrors
+    pub base_url: Option<String>,
+    /// Filter to apply
+    pub filter: Filter,
+    /// Category to search in
+    pub category: Category,
+    /// Page of the results, starting at 1
+    pub page: u32,
+    /// Text to search for
+    pub query: String,
+    /// Key used for sorting
+    pub sort: Option<Sort>,
+    /// Ordering of the sorted results
+    pub order: Option<Order>,
+    /// Only include entries from this user
+    pub user: Option<String>,
+    /// Whether to request the RSS feed instead of the html page
+    pub rss: bool,
+}
+
+impl SearchQuery {
+    /// Creates a query matching everything on the given source
+    pub fn new(source: Source) -> SearchQuery {
+        SearchQuery {
+            source: source,
+            base_url: None,
+            filter: Filter::default(),
+            category: Category::ALL,
+            page: 1,
+            query: String::new(),
+            sort: None,
+            order: None,
+            user: None,
+            rss: false,
+        }
+    }
+
+    /// Sets the url to use instead of the source's url
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.base_url = Some(base_url.to_owned());
+        self
+    }
+
+    /// Sets the filter to apply
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Sets the category to search in
+    pub fn category(mut self, category: Category) -> Self {
+        self.category = category;
+        self
+    }
+
+    /// Sets the page of the results, starting at 1
+    pub fn page(mut self, page: u32) -> Self {
+        self.page = page;
+        self
+    }
+
+    /// Sets the text to search for
+    pub fn query(mut self, query: &str) -> Self {
+        self.query = query.to_owned();
+        self
+    }
+
+    /// Sets the key used for sorting
+    pub fn sort(mut self, sort: Sort) -> Self {
+        self.sort = Some(sort);
+        self
+    }
+
+    /// Sets the ordering of the sorted results
+    pub fn order(mut self, order: Order) -> Self {
+        self.order = Some(order);
+        self
+    }
+
+    /// Only include entries from this user
+    pub fn user(mut self, user: &str) -> Self {
+        self.user = Some(user.to_owned());
+        self
+    }
+
+    /// Sets whether to request the RSS feed instead of the html page
+    pub fn rss(mut self, rss: bool) -> Self {
+        self.rss = rss;
+        self
+    }
+
+    /// Builds the url of this search.
+    ///
+    /// An error is returned if the category doesn't exist on the source, or if
+    /// the base url is invalid.
+    pub fn to_url(&self) -> Result<Url, Error> {
+        if !self.source.has_category(self.category) {
+            return Err(Error::Category(self.source, self.category));
+        }
+        if self.page == 0 {
+            return Err(Error::Page(self.page.to_string()));
+        }
+
+        let mut params = vec![
+            ("f", self.filter.code().to_string()),
+            ("c", self.category.to_string()),
+            ("p", self.page.to_string()),
+            ("q", self.query.clone()),
+        ];
+
+        if let Some(sort) = self.sort {
+            params.push(("s", sort.as_str().to_owned()));
+        }
+
+        if let Some(order) = self.order {
+            params.push(("o", order.as_str().to_owned()));
+        }
+
+        if let Some(ref user) = self.user {
+            params.push(("u", user.clone()));
+        }
+
+        if self.rss {
+            params.push(("page", "rss".to_owned()));
+        }
+
+        let base_url = match self.base_url {
+            Some(ref url) => url.as_str(),
+            None => self.source.base_url(),
+        };
+
+        Url::parse_with_params(base_url, params.iter())
+            .map_err(|e| Error::Url(format!("{}: {}", base_url, e)))
+    }
+
+    /// Parses the url of a search or user page back into a query.
+    ///
+    /// Urls on hosts other than nyaa.si and sukebei.nyaa.si are assumed to be
+    /// mirrors of nyaa.si, and have their origin kept as the base url.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nyaasi_scraper::search::{Category, SearchQuery, Sort, Source};
+    ///
+    /// let query = SearchQuery::from_url("https://sukebei.nyaa.si/user/Someone?c=1_4&s=seeders").unwrap();
+    ///
+    /// assert_eq!(query.source, Source::Sukebei);
+    /// assert_eq!(query.user, Some("Someone".to_owned()));
+    /// assert_eq!(query.category, Category::new(1, 4));
+    /// assert_eq!(query.sort, Some(Sort::Seeders));
+    /// ```
+    pub fn from_url(url: &str) -> Result<SearchQuery, Error> {
+        let parsed = Url::parse(url).map_err(|e| Error::Url(format!("{}: {}", url, e)))?;
+
+        let mut query = match parsed.host_str() {
+            Some("nyaa.si") => SearchQuery::new(Source::Nyaasi),
+            Some("sukebei.nyaa.si") => SearchQuery::new(Source::Sukebei),
+            _ => SearchQuery::new(Source::Nyaasi).base_url(&parsed.origin().ascii_serialization()),
+        };
+
+        let mut segments = parsed
+            .path_segments()
+            .map(|s| s.filter(|s| !s.is_empty()).collect::<Vec<_>>())
+            .unwrap_or_default();
+        if segments.len() == 2 && segments[0] == "user" {
+            query.user = Some(segments.remove(1).to_owned());
+        }
+
+        for (key, value) in parsed.query_pairs() {
+            match key.as_ref() {
+                "f" => {
+                    query.filter = value
+                        .parse::<u8>()
+                        .ok()
+                        .and_then(Filter::from_code)
+                        .ok_or_else(|| Error::Filter(value.to_string()))?
+                }
+                "c" => {
+                    query.category = parse_category(&value)
+                        .map(Category::from)
+                        .ok_or_else(|| Error::CategoryCode(value.to_string()))?
+                }
+                "p" => {
+                    query.page = value
+                        .parse::<u32>()
+                        .map_err(|_| Error::Page(value.to_string()))?
+                }
+                "q" => query.query = value.into_owned(),
+                "s" => query.sort = Some(value.parse()?),
+                "o" => query.order = Some(value.parse()?),
+                "u" => query.user = Some(value.into_owned()),
+                "page" => query.rss = value == "rss",
+                _ => {}
+            }
+        }
+
+        Ok(query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_url() {
+        assert_eq!(
+            SearchQuery::new(Source::Nyaasi).to_url().unwrap().as_str(),
+            "https://nyaa.si/?f=0&c=0_0&p=1&q="
+        );
+    }
+
+    #[test]
+    fn test_all_params() {
+        let url = SearchQuery::new(Source::Sukebei)
+            .base_url("https://mirror.example")
+            .filter(Filter::NoRemakes)
+            .category(Category::new(2, 2))
+            .page(3)
+            .query("a b")
+            .sort(Sort::Size)
+            .order(Order::Asc)
+            .user("Someone")
+            .rss(true)
+            .to_url()
+            .unwrap();
+        assert_eq!(
+            url.as_str(),
+            "https://mirror.example/?f=1&c=2_2&p=3&q=a+b&s=size&o=asc&u=Someone&page=rss"
+        );
+    }
+
+    #[test]
+    fn test_category_validation() {
+        // last subcategory of a category
+        assert!(SearchQuery::new(Source::Nyaasi)
+            .category(Category::new(1, 4))
+            .to_url()
+            .is_ok());
+        assert_eq!(
+            SearchQuery::new(Source::Nyaasi)
+                .category(Category::new(1, 5))
+                .to_url(),
+            Err(Error::Category(Source::Nyaasi, Category::new(1, 5)))
+        );
+        assert_eq!(
+            SearchQuery::new(Source::Sukebei)
+                .category(Category::new(3, 0))
+                .to_url(),
+            Err(Error::Category(Source::Sukebei, Category::new(3, 0)))
+        );
+    }
+
+    #[test]
+    fn test_category_name() {
+        assert_eq!(
+            Category::new(1, 2).name(Source::Nyaasi),
+            Some("Anime - English-translated".to_owned())
+        );
+        assert_eq!(
+            Category::new(2, 0).name(Source::Sukebei),
+            Some("Real Life".to_owned())
+        );
+        assert_eq!(Category::new(7, 0).name(Source::Nyaasi), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let query = SearchQuery::new(Source::Nyaasi)
+            .filter(Filter::TrustedOnly)
+            .category(Category::new(3, 1))
+            .page(2)
+            .query("some book")
+            .sort(Sort::Downloads)
+            .order(Order::Desc)
+            .user("Someone");
+        let url = query.to_url().unwrap();
+        assert_eq!(SearchQuery::from_url(url.as_str()), Ok(query));
+    }
+
+    #[test]
+    fn test_from_invalid_url() {
+        assert_eq!(
+            SearchQuery::from_url("https://nyaa.si/?s=name"),
+            Err(Error::Sort("name".to_owned()))
+        );
+        assert_eq!(
+            SearchQuery::from_url("https://nyaa.si/?f=9"),
+            Err(Error::Filter("9".to_owned()))
+        );
+    }
+}
@@ -1,7 +1,35 @@
+use std::fmt;
+
 lazy_static! {
     static ref SUFFIXES: Vec<&'static str> = vec!["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
 }
 
+/// Error returned when a size string can't be parsed
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The string is empty
+    Empty,
+    /// The number part of the string isn't a valid float
+    Number(String),
+    /// The string has no suffix
+    MissingSuffix,
+    /// The suffix isn't one of the supported suffixes
+    UnknownSuffix(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Empty => write!(f, "Empty size string"),
+            Error::Number(n) => write!(f, "Failed to parse {} as f64", n),
+            Error::MissingSuffix => write!(f, "Unable to find size suffix"),
+            Error::UnknownSuffix(s) => write!(f, "Unable to find suffix {} in suffixes list", s),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 /// Parses a size string such as "1.5 KiB" to a number in bytes.
 ///
 /// Supported suffixes are B, KiB, MiB, GiB, TiB, PiB, EiB
@@ -16,26 +44,26 @@ lazy_static! {
 ///
 /// assert_eq!(parsed, Ok(8192));
 /// ```
-pub fn parse(size: &str) -> Result<u64, String> {
+pub fn parse(size: &str) -> Result<u64, Error> {
     let mut parts = size.split_whitespace();
     let raw_number = parts.next();
     let parsed_float = match raw_number.and_then(|s| s.parse::<f64>().ok()) {
         Some(v) => v,
         None => {
             return Err(match raw_number {
-                Some(x) => format!("Failed to parse {} as f64", x),
-                None => "Empty size string".to_owned(),
+                Some(x) => Error::Number(x.to_owned()),
+                None => Error::Empty,
             })
         }
     };
     let raw_suffix = parts.next();
     if raw_suffix.is_none() {
-        return Err("Unable to find size suffix".to_owned());
+        return Err(Error::MissingSuffix);
     }
     let suffix = raw_suffix.unwrap();
     let suffix_idx = match SUFFIXES.iter().position(|v| v == &suffix) {
         Some(v) => v,
-        None => return Err(format!("Unable to find suffix {} in suffixes list", suffix)),
+        None => return Err(Error::UnknownSuffix(suffix.to_owned())),
     };
 
     Ok((parsed_float * (1 << (10 * suffix_idx)) as f64) as u64)
@@ -57,27 +85,21 @@ mod tests {
 
     #[test]
     fn test_empty() {
-        assert_eq!(Err("Empty size string".to_owned()), parse(""));
+        assert_eq!(Err(Error::Empty), parse(""));
     }
 
     #[test]
     fn test_invalid_float() {
-        assert_eq!(
-            Err("Failed to parse abc as f64".to_owned()),
-            parse("abc KiB")
-        );
+        assert_eq!(Err(Error::Number("abc".to_owned())), parse("abc KiB"));
     }
 
     #[test]
     fn test_no_suffix() {
-        assert_eq!(Err("Unable to find size suffix".to_owned()), parse("1.2"));
+        assert_eq!(Err(Error::MissingSuffix), parse("1.2"));
     }
 
     #[test]
     fn test_invalid_suffix() {
-        assert_eq!(
-            Err("Unable to find suffix bits in suffixes list".to_owned()),
-            parse("1.2 bits")
-        );
+        assert_eq!(Err(Error::UnknownSuffix("bits".to_owned())), parse("1.2 bits"));
     }
 }
@@ -0,0 +1,315 @@
+#[cfg(feature = "client")]
+use futures::future::{self, Future};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+/// Error returned when a page can't be fetched
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The request couldn't be sent, or the response couldn't be read
+    Request { url: String, reason: String },
+    /// A MockTransport has no response for the url
+    Unmatched { url: String },
+    /// The request budget for the host was used up by previous requests
+    BudgetExhausted { host: String, budget: u32 },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Request { url, reason } => write!(f, "Failed to fetch {}: {}", url, reason),
+            Error::Unmatched { url } => write!(f, "No response available for {}", url),
+            Error::BudgetExhausted { host, budget } => {
+                write!(f, "Budget of {} requests to {} exhausted", budget, host)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Response to a request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    /// HTTP status code
+    pub status: u16,
+    /// Headers, in the order they were received
+    pub headers: Vec<(String, String)>,
+    /// Body, decoded as text
+    pub body: String,
+}
+
+impl Response {
+    /// Creates a response without headers
+    pub fn new(status: u16, body: &str) -> Response {
+        Response {
+            status: status,
+            headers: Vec::new(),
+            body: body.to_owned(),
+        }
+    }
+
+    /// Adds a header to the response
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Returns the value of the first header with the given name, ignoring case
+    pub fn header_value(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Whether the status is a success (2xx) status
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// Fetches pages. All requests made by the clients and the command line
+/// tool go through a transport, so the HTTP stack can be replaced, for
+/// example to serve canned responses in tests.
+///
+/// Responses with error statuses should be returned as responses, errors
+/// are reserved for requests that got no response at all.
+pub trait Transport {
+    /// Fetches a url with a GET request
+    fn get(&self, url: &str) -> Result<Response, Error>;
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn get(&self, url: &str) -> Result<Response, Error> {
+        (**self).get(url)
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for &T {
+    fn get(&self, url: &str) -> Result<Response, Error> {
+        (**self).get(url)
+    }
+}
+
+/// Future of the response to a request made with an AsyncTransport
+#[cfg(feature = "client")]
+pub type ResponseFuture = Box<dyn Future<Item = Response, Error = Error> + Send>;
+
+/// Fetches pages without blocking. All requests made by the async client go
+/// through an async transport, so they can be served from canned responses
+/// like the ones of a Transport.
+///
+/// Like with Transport, responses with error statuses should be returned as
+/// responses.
+#[cfg(feature = "client")]
+pub trait AsyncTransport {
+    /// Fetches a url with a GET request
+    fn get_async(&self, url: &str) -> ResponseFuture;
+}
+
+#[cfg(feature = "client")]
+impl<T: AsyncTransport + ?Sized> AsyncTransport for Box<T> {
+    fn get_async(&self, url: &str) -> ResponseFuture {
+        (**self).get_async(url)
+    }
+}
+
+#[cfg(feature = "client")]
+impl<T: AsyncTransport + ?Sized> AsyncTransport for &T {
+    fn get_async(&self, url: &str) -> ResponseFuture {
+        (**self).get_async(url)
+    }
+}
+
+/// Transport that serves canned responses, keyed by url. Requests for urls
+/// without a response fail with `Error::Unmatched`.
+///
+/// # Examples
+///
+/// ```
+/// use nyaasi_scraper::transport::{MockTransport, Response, Transport};
+///
+/// let transport = MockTransport::new()
+///     .with("https://nyaa.si/", Response::new(200, "<html></html>"));
+///
+/// assert_eq!(transport.get("https://nyaa.si/").unwrap().body, "<html></html>");
+/// assert!(transport.get("https://nyaa.si/view/1").is_err());
+/// assert_eq!(transport.requests(), vec!["https://nyaa.si/", "https://nyaa.si/view/1"]);
+/// ```
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    responses: HashMap<String, Response>,
+    requests: Mutex<Vec<String>>,
+}
+
+impl MockTransport {
+    /// Creates a transport without responses
+    pub fn new() -> MockTransport {
+        MockTransport::default()
+    }
+
+    /// Adds the response for a url, replacing the previous one
+    pub fn with(mut self, url: &str, response: Response) -> Self {
+        self.insert(url, response);
+        self
+    }
+
+    /// Adds the response for a url, replacing the previous one
+    pub fn insert(&mut self, url: &str, response: Response) {
+        self.responses.insert(url.to_owned(), response);
+    }
+
+    /// Returns the urls requested so far, in order
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().expect("Poisoned lock").clone()
+    }
+}
+
+impl Transport for MockTransport {
+    fn get(&self, url: &str) -> Result<Response, Error> {
+        self.requests
+            .lock()
+            .expect("Poisoned lock")
+            .push(url.to_owned());
+        self.responses
+            .get(url)
+            .cloned()
+            .ok_or_else(|| Error::Unmatched {
+                url: url.to_owned(),
+            })
+    }
+}
+
+/// Responds immediately, with the same responses as the blocking transport
+#[cfg(feature = "client")]
+impl AsyncTransport for MockTransport {
+    fn get_async(&self, url: &str) -> ResponseFuture {
+        Box::new(future::result(self.get(url)))
+    }
+}
+
+/// Transport that makes requests with reqwest
+#[cfg(feature = "client")]
+#[derive(Debug)]
+pub struct HttpTransport {
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "client")]
+impl HttpTransport {
+    /// Creates a transport using the given client
+    pub fn new(client: reqwest::Client) -> HttpTransport {
+        HttpTransport { client: client }
+    }
+}
+
+#[cfg(feature = "client")]
+impl Default for HttpTransport {
+    fn default() -> HttpTransport {
+        HttpTransport::new(reqwest::Client::new())
+    }
+}
+
+#[cfg(feature = "client")]
+impl Transport for HttpTransport {
+    fn get(&self, url: &str) -> Result<Response, Error> {
+        let failed = |e: reqwest::Error| Error::Request {
+            url: url.to_owned(),
+            reason: e.to_string(),
+        };
+        let mut response = self.client.get(url).send().map_err(failed)?;
+        Ok(Response {
+            status: response.status().as_u16(),
+            headers: headers(response.headers()),
+            body: response.text().map_err(failed)?,
+        })
+    }
+}
+
+/// Async transport that makes requests with reqwest. The futures must be run
+/// on a tokio runtime.
+#[cfg(feature = "client")]
+#[derive(Debug, Clone)]
+pub struct AsyncHttpTransport {
+    client: reqwest::r#async::Client,
+}
+
+#[cfg(feature = "client")]
+impl AsyncHttpTransport {
+    /// Creates a transport using the given client
+    pub fn new(client: reqwest::r#async::Client) -> AsyncHttpTransport {
+        AsyncHttpTransport { client: client }
+    }
+}
+
+#[cfg(feature = "client")]
+impl Default for AsyncHttpTransport {
+    fn default() -> AsyncHttpTransport {
+        AsyncHttpTransport::new(reqwest::r#async::Client::new())
+    }
+}
+
+#[cfg(feature = "client")]
+impl AsyncTransport for AsyncHttpTransport {
+    fn get_async(&self, url: &str) -> ResponseFuture {
+        let target = url.to_owned();
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .and_then(|mut response| {
+                let status = response.status().as_u16();
+                let headers = headers(response.headers());
+                response.text().map(move |body| Response {
+                    status: status,
+                    headers: headers,
+                    body: body,
+                })
+            })
+            .map_err(move |e| Error::Request {
+                url: target,
+                reason: e.to_string(),
+            });
+        Box::new(response)
+    }
+}
+
+/// Headers of a reqwest response, without the ones that aren't valid text
+#[cfg(feature = "client")]
+fn headers(headers: &reqwest::header::HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_owned())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_value() {
+        let response = Response::new(429, "")
+            .header("Retry-After", "10")
+            .header("retry-after", "20");
+        assert_eq!(response.header_value("retry-after"), Some("10"));
+        assert_eq!(response.header_value("Location"), None);
+        assert!(!response.is_success());
+    }
+
+    #[test]
+    fn test_mock() {
+        let mut transport = MockTransport::new().with("a", Response::new(200, "first"));
+        transport.insert("a", Response::new(404, "replaced"));
+        let boxed: Box<dyn Transport> = Box::new(transport);
+        assert_eq!(boxed.get("a"), Ok(Response::new(404, "replaced")));
+        assert_eq!(
+            boxed.get("b"),
+            Err(Error::Unmatched {
+                url: "b".to_owned()
+            })
+        );
+    }
+}
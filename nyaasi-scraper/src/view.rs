@@ -0,0 +1,592 @@
+use kuchiki::traits::*;
+use kuchiki::NodeRef;
+use serde_derive::Serialize;
+
+use super::{
+    attr, entry_id, href, parse_integer, parse_magnet, parse_url, select, select_all,
+    select_parent, select_parent_href, select_text, size_parser, timestamp, try_attr, EntryKind,
+    Links, ParseError, Sizes,
+};
+
+/// Details about a single entry, scraped from its page (`https://nyaa.si/view/<id>`)
+#[derive(Debug, Serialize)]
+pub struct NyaasiTorrentDetails {
+    /// URL of this entry
+    pub url: String,
+    /// Numeric id of this entry, taken from its url (`/view/<id>`)
+    pub id: u64,
+    /// Type of this entry
+    pub kind: EntryKind,
+    /// Name of the entry
+    pub name: String,
+    /// Category of the entry, as displayed on the page (eg "Anime - English-translated")
+    pub category: String,
+    /// Name of the user who submitted this entry, or None if it was submitted anonymously
+    pub submitter: Option<String>,
+    /// Information link provided by the submitter, if any
+    pub information: Option<String>,
+    /// Info hash of the torrent
+    pub info_hash: String,
+    /// Number of files in the torrent, or None if the file list isn't displayed
+    pub file_count: Option<u32>,
+    /// Files in the torrent, or None if the file list isn't displayed (nyaa
+    /// hides it for torrents with too many files)
+    pub files: Option<FileList>,
+    /// Download links
+    pub links: Links,
+    /// Entry size
+    pub sizes: Sizes,
+    /// Date added
+    pub date: String,
+    /// Date added, as an unix timestamp in seconds
+    pub timestamp: i64,
+    /// Number of seeders
+    pub seeders: u32,
+    /// Number of leechers
+    pub leechers: u32,
+    /// Number of downloads completed
+    pub downloads: u32,
+    /// Description of the entry, in markdown
+    pub description: String,
+    /// Comments on this entry, oldest first
+    pub comments: Vec<Comment>,
+}
+
+/// Role of an user on the site
+#[derive(Debug, PartialEq, Serialize)]
+pub enum UserRole {
+    /// A regular user
+    User,
+    /// A trusted user
+    Trusted,
+    /// A moderator
+    Moderator,
+    /// An administrator
+    Administrator,
+    /// A banned user
+    Banned,
+    /// This user's role isn't any of the others
+    Unknown(String),
+}
+
+impl UserRole {
+    fn from_title(title: &str) -> Self {
+        match title {
+            "User" => Self::User,
+            "Trusted" => Self::Trusted,
+            "Moderator" => Self::Moderator,
+            "Administrator" => Self::Administrator,
+            "BANNED" => Self::Banned,
+            other => Self::Unknown(other.to_owned()),
+        }
+    }
+}
+
+/// A comment on an entry
+#[derive(Debug, Serialize)]
+pub struct Comment {
+    /// Name of the user who wrote this comment
+    pub author: String,
+    /// Role of the user who wrote this comment
+    pub author_role: UserRole,
+    /// Date the comment was posted, as displayed on the page
+    pub date: String,
+    /// Date the comment was posted, as an unix timestamp in seconds
+    pub timestamp: i64,
+    /// Whether this comment was edited after being posted
+    pub edited: bool,
+    /// Contents of the comment, in markdown
+    pub body: String,
+}
+
+/// Parses HTML source of an entry's page and the page's url into a more usable format.
+///
+/// The same url used for fetching the html string should be passed as the second
+/// parameter for this function.
+///
+/// An error is returned if parsing fails.
+pub fn parse_view_html(html: &str, url: &str) -> Result<NyaasiTorrentDetails, ParseError> {
+    let current_url = parse_url(url)?;
+    let dom = kuchiki::parse_html().one(html);
+
+    let panel = select_parent(&dom, "div.panel > div.panel-body > div.row")?
+        .parent()
+        .ok_or_else(|| ParseError::MissingParent {
+            selector: "div.panel > div.panel-body".to_owned(),
+            row: None,
+        })?;
+
+    let raw_magnet = select_parent_href(&panel, "div.panel-footer a > i.fa-magnet", &current_url)?;
+    let magnet = parse_magnet(&raw_magnet);
+    let magnet_size = magnet.as_ref().and_then(|m| m.length());
+    let raw_size = field_text(&panel, "File size:")?;
+
+    let submitter = field(&panel, "Submitter:")?;
+    let information = field(&panel, "Information:")?;
+    let files = match dom.select_first("div.torrent-file-list > ul") {
+        Ok(ul) => Some(FileList::new(parse_file_nodes(ul.as_node(), "")?)),
+        Err(()) => None,
+    };
+
+    Ok(NyaasiTorrentDetails {
+        url: current_url.as_str().to_owned(),
+        id: entry_id("page url", current_url.as_str())?,
+        kind: panel_kind(&panel)?,
+        name: trimmed_text(&select(&panel, "div.panel-heading > h3.panel-title")?),
+        category: field_text(&panel, "Category:")?,
+        submitter: submitter
+            .select_first("a")
+            .ok()
+            .map(|a| trimmed_text(a.as_node())),
+        information: match information.select_first("a") {
+            Ok(a) => Some(href(a.as_node(), &current_url)?),
+            Err(()) => None,
+        },
+        info_hash: field_text(&panel, "Info hash:")?,
+        file_count: files.as_ref().map(|f| f.files().len() as u32),
+        files: files,
+        links: Links {
+            torrent: select_parent_href(
+                &panel,
+                "div.panel-footer a > i.fa-download",
+                &current_url,
+            )?,
+            magnet: raw_magnet,
+            parsed_magnet: magnet,
+        },
+        sizes: Sizes {
+            raw: raw_size.clone(),
+            parsed_from_magnet: magnet_size,
+            parsed_from_raw: size_parser::parse(&raw_size).ok(),
+        },
+        date: field_text(&panel, "Date:")?,
+        timestamp: timestamp(&field(&panel, "Date:")?, "Date:")?,
+        seeders: field_u32(&panel, "Seeders:")?,
+        leechers: field_u32(&panel, "Leechers:")?,
+        downloads: field_u32(&panel, "Completed:")?,
+        description: select(&dom, "#torrent-description")?
+            .text_contents()
+            .trim()
+            .to_owned(),
+        comments: select_all(&dom, "#comments div.comment-panel")?
+            .map(|panel| parse_comment(panel.as_node()))
+            .collect::<Result<Vec<_>, ParseError>>()?,
+    })
+}
+
+fn parse_comment(panel: &NodeRef) -> Result<Comment, ParseError> {
+    let author = select(panel, "div.col-md-2 > p > a")?;
+    let posted = select(panel, "div.comment-details > a > small")?;
+
+    Ok(Comment {
+        author: trimmed_text(&author),
+        author_role: match try_attr(&author, "title")? {
+            Some(title) => UserRole::from_title(&title),
+            None => UserRole::User,
+        },
+        date: trimmed_text(&posted),
+        timestamp: timestamp(&posted, "div.comment-details > a > small")?,
+        edited: panel.select_first("div.comment-details > small").is_ok(),
+        body: select_text(panel, "div.comment-content")?.trim().to_owned(),
+    })
+}
+
+/// File list of an entry
+#[derive(Debug, Serialize)]
+pub struct FileList {
+    /// Top level files and folders
+    pub nodes: Vec<FileNode>,
+    /// Sum of the sizes of all files, or None if any of them couldn't be parsed
+    pub total_size: Option<u64>,
+}
+
+impl FileList {
+    fn new(nodes: Vec<FileNode>) -> FileList {
+        FileList {
+            total_size: sum_sizes(&nodes),
+            nodes: nodes,
+        }
+    }
+
+    /// Returns all files in the list, in the order they're displayed, excluding folders.
+    pub fn files(&self) -> Vec<&FileNode> {
+        let mut files = Vec::new();
+        for node in self.nodes.iter() {
+            node.collect_files(&mut files);
+        }
+        files
+    }
+}
+
+/// A file or folder in the file list of an entry
+#[derive(Debug, Serialize)]
+pub enum FileNode {
+    /// A folder and its contents
+    Folder {
+        /// Path of the folder, with components separated by `/`
+        path: String,
+        /// Files and folders inside this folder
+        children: Vec<FileNode>,
+    },
+    /// A single file
+    File {
+        /// Path of the file, with components separated by `/`
+        path: String,
+        /// Raw, human readable string representing the size
+        raw_size: String,
+        /// Size parsed from the raw string
+        size: Option<u64>,
+    },
+}
+
+impl FileNode {
+    /// Path of this node, with components separated by `/`
+    pub fn path(&self) -> &str {
+        match self {
+            FileNode::Folder { path, .. } => path,
+            FileNode::File { path, .. } => path,
+        }
+    }
+
+    /// Name of this node, which is the last component of its path
+    pub fn name(&self) -> &str {
+        let path = self.path();
+        path.rsplit('/').next().unwrap_or(path)
+    }
+
+    /// Size of this node. For folders, this is the sum of the sizes of
+    /// their contents, or None if any of them couldn't be parsed.
+    pub fn size(&self) -> Option<u64> {
+        match self {
+            FileNode::Folder { children, .. } => sum_sizes(children),
+            FileNode::File { size, .. } => *size,
+        }
+    }
+
+    /// Returns whether this node is a folder
+    pub fn is_folder(&self) -> bool {
+        match self {
+            FileNode::Folder { .. } => true,
+            FileNode::File { .. } => false,
+        }
+    }
+
+    fn collect_files<'a>(&'a self, files: &mut Vec<&'a FileNode>) {
+        match self {
+            FileNode::Folder { children, .. } => {
+                for child in children.iter() {
+                    child.collect_files(files);
+                }
+            }
+            FileNode::File { .. } => files.push(self),
+        }
+    }
+}
+
+fn sum_sizes(nodes: &[FileNode]) -> Option<u64> {
+    nodes.iter().map(FileNode::size).sum()
+}
+
+/// Parses the `li` children of a file list `ul` element.
+fn parse_file_nodes(ul: &NodeRef, parent: &str) -> Result<Vec<FileNode>, ParseError> {
+    ul.children()
+        .elements()
+        .filter(|li| &*li.name.local == "li")
+        .map(|li| {
+            let li = li.as_node();
+            match child_element(li, "a") {
+                Some(a) => {
+                    let path = join_path(parent, &trimmed_text(&a));
+                    let children = match child_element(li, "ul") {
+                        Some(ul) => parse_file_nodes(&ul, &path)?,
+                        None => Vec::new(),
+                    };
+                    Ok(FileNode::Folder {
+                        path: path,
+                        children: children,
+                    })
+                }
+                None => {
+                    let name = li
+                        .children()
+                        .text_nodes()
+                        .map(|t| t.borrow().clone())
+                        .collect::<String>();
+                    let raw_size = select(li, "span.file-size")?
+                        .text_contents()
+                        .trim()
+                        .trim_start_matches('(')
+                        .trim_end_matches(')')
+                        .to_owned();
+                    Ok(FileNode::File {
+                        path: join_path(parent, name.trim()),
+                        size: size_parser::parse(&raw_size).ok(),
+                        raw_size: raw_size,
+                    })
+                }
+            }
+        })
+        .collect()
+}
+
+#[inline]
+fn child_element(node: &NodeRef, name: &str) -> Option<NodeRef> {
+    node.children()
+        .elements()
+        .find(|e| &*e.name.local == name)
+        .map(|e| e.as_node().clone())
+}
+
+#[inline]
+fn join_path(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}/{}", parent, name)
+    }
+}
+
+fn panel_kind(panel: &NodeRef) -> Result<EntryKind, ParseError> {
+    let classes = attr(panel, "class")?;
+    classes
+        .split_whitespace()
+        .filter(|c| *c != "panel")
+        .find_map(|c| {
+            if c.starts_with("panel-") {
+                Some(EntryKind::from_class_name(&c["panel-".len()..]))
+            } else {
+                None
+            }
+        })
+        .ok_or_else(|| ParseError::MissingEntryKind {
+            classes: classes.clone(),
+        })
+}
+
+/// Finds the value element for a label such as "Seeders:" in the information panel.
+fn field(panel: &NodeRef, label: &str) -> Result<NodeRef, ParseError> {
+    select_all(panel, "div.row > div.col-md-1")?
+        .find(|e| e.text_contents().trim() == label)
+        .and_then(|e| e.as_node().following_siblings().elements().next())
+        .map(|e| e.as_node().clone())
+        .ok_or_else(|| ParseError::MissingField {
+            label: label.to_owned(),
+        })
+}
+
+#[inline]
+fn field_text(panel: &NodeRef, label: &str) -> Result<String, ParseError> {
+    field(panel, label).map(|e| trimmed_text(&e))
+}
+
+#[inline]
+fn field_u32(panel: &NodeRef, label: &str) -> Result<u32, ParseError> {
+    parse_integer(label, field_text(panel, label)?)
+}
+
+/// Text contents of a node, with runs of whitespace collapsed into a single space.
+#[inline]
+fn trimmed_text(node: &NodeRef) -> String {
+    node.text_contents()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VIEW: &str = include_str!("../tests/fixtures/view.html");
+
+    fn url() -> String {
+        let meta: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/view.json")).unwrap();
+        meta["url"].as_str().unwrap().to_owned()
+    }
+
+    fn parse(html: &str) -> NyaasiTorrentDetails {
+        parse_view_html(html, &url()).unwrap()
+    }
+
+    #[test]
+    fn test_details() {
+        let details = parse(VIEW);
+        assert_eq!(details.url, "https://nyaa.si/view/1183320");
+        assert_eq!(details.id, 1183320);
+        match details.kind {
+            EntryKind::Trusted => {}
+            other => panic!("Expected a trusted entry, got {:?}", other),
+        }
+        assert_eq!(details.name, "[Group] Show - 02 [1080p].mkv");
+        assert_eq!(details.category, "Anime - English-translated");
+        assert_eq!(details.submitter.as_deref(), Some("Uploader"));
+        assert_eq!(
+            details.information.as_deref(),
+            Some("https://example.com/show")
+        );
+        assert_eq!(
+            details.info_hash,
+            "a544af123662c61ae0664b4d41457639d24ed7f1"
+        );
+        assert_eq!(
+            (details.seeders, details.leechers, details.downloads),
+            (312, 27, 1520)
+        );
+        assert_eq!(
+            details.description,
+            "Second episode of **Show**.\n\nEncoded from the Blu-ray."
+        );
+        assert_eq!(details.date, "2019-09-16 13:02 UTC");
+        assert_eq!(details.timestamp, 1568638920);
+        assert_eq!(details.sizes.raw, "1.4 GiB");
+        assert_eq!(details.sizes.parsed_from_raw, Some(1503238553));
+    }
+
+    #[test]
+    fn test_links() {
+        let links = parse(VIEW).links;
+        assert_eq!(links.torrent, "https://nyaa.si/download/1183320.torrent");
+        let magnet = links.parsed_magnet.unwrap();
+        assert_eq!(magnet.to_string(), links.magnet);
+        assert_eq!(
+            magnet.info_hash().map(|h| h.to_hex()).as_deref(),
+            Some("a544af123662c61ae0664b4d41457639d24ed7f1")
+        );
+        assert_eq!(magnet.name(), Some("[Group] Show - 02 [1080p].mkv"));
+        assert_eq!(magnet.trackers().len(), 2);
+    }
+
+    #[test]
+    fn test_anonymous() {
+        let html = VIEW
+            .replace(
+                r#"<a class="text-success" href="/user/Uploader" data-toggle="tooltip" title="Trusted">Uploader</a>"#,
+                "Anonymous",
+            )
+            .replace(
+                r#"<a href="https://example.com/show">https://example.com/show</a>"#,
+                "No information.",
+            );
+        let details = parse(&html);
+        assert_eq!(details.submitter, None);
+        assert_eq!(details.information, None);
+    }
+
+    #[test]
+    fn test_file_list() {
+        let details = parse(VIEW);
+        assert_eq!(details.file_count, Some(4));
+        let files = details.files.unwrap();
+        assert_eq!(files.total_size, Some(1493173248));
+        assert_eq!(files.nodes.len(), 2);
+
+        let folder = &files.nodes[0];
+        assert!(folder.is_folder());
+        assert_eq!(folder.path(), "[Group] Show - 02 [1080p]");
+        assert_eq!(folder.size(), Some(1493172224));
+        let extras = match folder {
+            FileNode::Folder { children, .. } => &children[1],
+            other => panic!("Expected folder, got {:?}", other),
+        };
+        assert_eq!(extras.path(), "[Group] Show - 02 [1080p]/Extras");
+        assert_eq!(extras.name(), "Extras");
+        assert_eq!(extras.size(), Some(150994944));
+
+        let paths = files.files().iter().map(|f| f.path()).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                "[Group] Show - 02 [1080p]/[Group] Show - 02 [1080p].mkv",
+                "[Group] Show - 02 [1080p]/Extras/NCOP.mkv",
+                "[Group] Show - 02 [1080p]/Extras/NCED.mkv",
+                "readme.txt",
+            ]
+        );
+        match files.files()[1] {
+            FileNode::File { raw_size, size, .. } => {
+                assert_eq!(raw_size, "80 MiB");
+                assert_eq!(*size, Some(83886080));
+            }
+            other => panic!("Expected file, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_hidden_file_list() {
+        // nyaa leaves the panel empty for torrents with too many files
+        let start = VIEW.find(r#"<div class="torrent-file-list"#).unwrap();
+        let end = VIEW.find(r#"<div id="comments""#).unwrap();
+        let html = format!("{}</div>{}", &VIEW[..start], &VIEW[end..]);
+        let details = parse(&html);
+        assert_eq!(details.file_count, None);
+        assert!(details.files.is_none());
+    }
+
+    #[test]
+    fn test_unparsed_size() {
+        let file = |path: &str, raw_size: &str| FileNode::File {
+            path: path.to_owned(),
+            raw_size: raw_size.to_owned(),
+            size: size_parser::parse(raw_size).ok(),
+        };
+        let list = FileList::new(vec![
+            FileNode::Folder {
+                path: "a".to_owned(),
+                children: vec![file("a/b", "1 KiB"), file("a/c", "??")],
+            },
+            file("d", "2 KiB"),
+        ]);
+        assert_eq!(list.total_size, None);
+        assert_eq!(list.nodes[0].size(), None);
+        assert_eq!(list.nodes[1].size(), Some(2048));
+        assert_eq!(list.files().len(), 3);
+    }
+
+    #[test]
+    fn test_comments() {
+        let comments = parse(VIEW).comments;
+        let authors = comments
+            .iter()
+            .map(|c| (c.author.as_str(), &c.author_role))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            authors,
+            vec![
+                ("Viewer", &UserRole::User),
+                ("Uploader", &UserRole::Trusted),
+                ("Mod", &UserRole::Moderator),
+                ("Spammer", &UserRole::Banned),
+                ("Helper", &UserRole::Unknown("Helper".to_owned())),
+                // users without a title are regular users
+                ("Someone", &UserRole::User),
+            ]
+        );
+
+        let first = &comments[0];
+        assert_eq!(first.date, "2019-09-16 13:12 UTC");
+        assert_eq!(first.timestamp, 1568639520);
+        assert!(!first.edited);
+        assert_eq!(first.body, "Thanks for the release!");
+
+        let edited = &comments[1];
+        assert!(edited.edited);
+        // the posting date is kept, not the edit date
+        assert_eq!(edited.timestamp, 1568640120);
+        assert_eq!(edited.body, "A v2 fixing the subtitles is coming.");
+        assert_eq!(
+            comments[3].body,
+            "Free downloads at [example](https://example.com)"
+        );
+        assert_eq!(comments.iter().filter(|c| c.edited).count(), 1);
+    }
+
+    #[test]
+    fn test_missing_field() {
+        let html = VIEW.replace("Completed:", "Done:");
+        match parse_view_html(&html, &url()) {
+            Err(ParseError::MissingField { label }) => assert_eq!(label, "Completed:"),
+            other => panic!("Expected missing field, got {:?}", other),
+        }
+    }
+}
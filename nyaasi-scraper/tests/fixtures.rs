@@ -0,0 +1,166 @@
+//! Regression tests for parse_html().
+//!
+//! Each fixture in `tests/fixtures` is a saved page (`<name>.html`), the
+//! metadata saved next to it by `nyaasi --record` (`<name>.json`) and the
+//! results it should parse to (`<name>.expected.json`).
+//!
+//! Fixtures of modified sites also have the selector profile used to parse
+//! them (`<name>.profile.json`). Pages that aren't search pages have no
+//! expected results, their tests check the error instead.
+//!
+//! Run with `UPDATE_FIXTURES=1` to rewrite the expected results after a
+//! deliberate change to the parser, and review the diff.
+
+use nyaasi_scraper::{
+    parse_html, parse_html_with, parse_html_with_options, ParseError, ParseOptions, Results,
+    SelectorProfile,
+};
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+fn fixture_path(file: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(file)
+}
+
+fn read_fixture(file: &str) -> String {
+    let path = fixture_path(file);
+    fs::read_to_string(&path).unwrap_or_else(|e| panic!("Failed to read {:?}: {}", path, e))
+}
+
+fn url(name: &str) -> String {
+    let meta: Value = serde_json::from_str(&read_fixture(&format!("{}.json", name))).unwrap();
+    meta["url"]
+        .as_str()
+        .expect("Fixture metadata has no url")
+        .to_owned()
+}
+
+fn profile(name: &str) -> SelectorProfile {
+    serde_json::from_str(&read_fixture(&format!("{}.profile.json", name))).unwrap()
+}
+
+fn parse_fixture(name: &str) -> Result<Results, ParseError> {
+    parse_html(&read_fixture(&format!("{}.html", name)), &url(name))
+}
+
+fn check(name: &str) {
+    check_with(name, parse_html);
+}
+
+fn check_with<F>(name: &str, parse: F)
+where
+    F: Fn(&str, &str) -> Result<Results, ParseError>,
+{
+    let html = read_fixture(&format!("{}.html", name));
+    let results = parse(&html, &url(name)).unwrap();
+
+    let expected_file = format!("{}.expected.json", name);
+    if env::var_os("UPDATE_FIXTURES").is_some() {
+        let serialized = serde_json::to_string_pretty(&results).unwrap();
+        fs::write(fixture_path(&expected_file), serialized + "\n").unwrap();
+        return;
+    }
+
+    let expected: Results = serde_json::from_str(&read_fixture(&expected_file)).unwrap();
+    assert_eq!(results, expected, "Results of {} changed", name);
+}
+
+fn check_not_a_search_page(name: &str, title: &str) {
+    let expected = ParseError::NotASearchPage {
+        title: title.to_owned(),
+    };
+    assert_eq!(parse_fixture(name), Err(expected));
+}
+
+#[test]
+fn test_search() {
+    check("search");
+}
+
+#[test]
+fn test_page_window() {
+    check("window");
+    let pagination = parse_fixture("window").unwrap().pagination.unwrap();
+    assert_eq!(pagination.current.number, 5);
+    assert_eq!(pagination.pages.iter().map(|p| p.number).max(), Some(7));
+    assert_eq!(pagination.last_page, 14);
+}
+
+#[test]
+fn test_user() {
+    check("user");
+}
+
+#[test]
+fn test_empty() {
+    check("empty");
+    assert_eq!(parse_fixture("empty"), Ok(Results::empty()));
+}
+
+#[test]
+fn test_captcha() {
+    check_not_a_search_page("captcha", "Just a moment...");
+}
+
+#[test]
+fn test_maintenance() {
+    check_not_a_search_page("maintenance", "Maintenance :: Nyaa");
+}
+
+#[test]
+fn test_not_found() {
+    check_not_a_search_page("notfound", "404 Not Found :: Nyaa");
+}
+
+#[test]
+fn test_sukebei() {
+    check("sukebei");
+}
+
+#[test]
+fn test_entry_kinds() {
+    check("kinds");
+}
+
+#[test]
+fn test_selector_profile() {
+    let profile = profile("fork");
+    check_with("fork", |html, url| parse_html_with(&profile, html, url));
+
+    assert_eq!(parse_fixture("fork"), Err(ParseError::MissingTable));
+}
+
+#[test]
+fn test_lenient() {
+    let lenient = ParseOptions {
+        lenient: true,
+        ..ParseOptions::default()
+    };
+    check_with("broken", |html, url| {
+        parse_html_with_options(html, url, &lenient)
+    });
+
+    let results =
+        parse_html_with_options(&read_fixture("broken.html"), &url("broken"), &lenient).unwrap();
+    assert_eq!(results.entries.len(), 2);
+    assert!(results.entries.iter().all(|e| e.id != 1183311));
+    assert_eq!(results.warnings.len(), 1);
+    assert_eq!(results.warnings[0].row, 1);
+
+    match parse_fixture("broken") {
+        Err(ParseError::BadInteger { row, value, .. }) => {
+            assert_eq!(row, Some(1));
+            assert_eq!(value, "-");
+        }
+        other => panic!("Expected a bad integer, got {:?}", other),
+    }
+    assert_eq!(
+        results.warnings[0].error,
+        parse_fixture("broken").unwrap_err()
+    );
+}
@@ -1,158 +1,125 @@
-use clap::{App, AppSettings, Arg, ArgMatches};
-use url::Url;
+use super::record;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use std::fs;
+use std::time::Duration;
+
+use nyaasi_scraper::retry::RetryPolicy;
+use nyaasi_scraper::search::{Category, CategoryInfo, Filter, Order, SearchQuery, Sort, Source};
+use nyaasi_scraper::SelectorProfile;
 
 lazy_static! {
     static ref ARGS: ArgMatches<'static> = parse_args();
+    static ref SORT_KEYS: Vec<&'static str> = Sort::ALL.iter().map(Sort::as_str).collect();
+    static ref ORDERING_TYPES: Vec<&'static str> = Order::ALL.iter().map(Order::as_str).collect();
+}
 
-    // Values valid as of https://github.com/nyaadevs/nyaa/blob/5da76351640909cf3a992150e88a4a314212f077/nyaa/search.py
-    static ref FILTERS: Vec<&'static str> = vec!["No filter", "No remakes", "Trusted only", "Complete",];
-    static ref SORT_KEYS: Vec<&'static str> = vec!["id", "size", "comments", "seeders", "leechers", "downloads"];
-    static ref ORDERING_TYPES: Vec<&'static str> = vec!["asc", "desc"];
-    static ref NYAASI_CATEGORIES: Vec<Category> = vec![
-        Category::from("All categories", vec![]),
-        Category::from(
-            "Anime",
-            vec![
-                "Anime Music Video",
-                "English-translated",
-                "Non-English-translated",
-                "Raw"
-            ]
-        ),
-        Category::from("Audio", vec!["Lossless", "Lossy"]),
-        Category::from(
-            "Literature",
-            vec!["English-translated", "Non-English-translated", "Raw"]
-        ),
-        Category::from(
-            "Live Action",
-            vec![
-                "English-translated",
-                "Idol/Promotional Video",
-                "Non-English-translated",
-                "Raw"
-            ]
-        ),
-        Category::from("Pictures", vec!["Graphics", "Photos"]),
-        Category::from("Software", vec!["Applications", "Games"]),
-    ];
-    static ref SUKEBEI_CATEGORIES: Vec<Category> = vec![
-        Category::from("All categories", vec![]),
-        Category::from(
-            "Art",
-            vec!["Anime", "Doujinshi", "Games", "Manga", "Pictures"]
-        ),
-        Category::from("Real Life", vec!["Photobooks and Pictures", "Videos"]),
-    ];
+fn category_names(list: &[CategoryInfo]) -> String {
+    list.iter()
+        .enumerate()
+        .map(|(index, category)| format!("{} - {}", index, category.name))
+        .collect::<Vec<String>>()
+        .join("\n")
 }
 
-enum Source {
-    NYAASI,
-    SUKEBEI,
+fn category_names_and_subcategories(list: &[CategoryInfo]) -> String {
+    list.iter()
+        .enumerate()
+        .map(|(index, category)| {
+            if category.subcategories.len() == 0 {
+                format!("{} - {}", index, category.name)
+            } else {
+                format!(
+                    "{} - {}\n{}",
+                    index,
+                    category.name,
+                    category
+                        .subcategories
+                        .iter()
+                        .enumerate()
+                        .map(|(i, s)| format!("   {} - {}", i + 1, s))
+                        .collect::<Vec<String>>()
+                        .join("\n")
+                )
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
 }
 
-impl Source {
-    fn categories(&self) -> &'static Vec<Category> {
-        match self {
-            Source::NYAASI => &NYAASI_CATEGORIES,
-            Source::SUKEBEI => &SUKEBEI_CATEGORIES,
-        }
-    }
+pub fn include_amount() -> Result<usize, String> {
+    try_parse("number", 10000000usize)
+}
 
-    fn base_url(&self) -> &'static str {
-        match self {
-            Source::NYAASI => "https://nyaa.si",
-            Source::SUKEBEI => "https://sukebei.nyaa.si",
-        }
+/// Maximum number of pages to fetch, or None if all pages should be fetched
+pub fn max_pages() -> Result<Option<u32>, String> {
+    if ARGS.is_present("all") {
+        return Ok(None);
     }
+    match try_parse("pages", 1u32)? {
+        0 => Err("Invalid value for pages: must be at least 1".to_owned()),
+        pages => Ok(Some(pages)),
+    }
+}
 
-    fn parse_categories(&self) -> Result<(usize, usize), String> {
-        let categories = self.categories();
-        let category_idx = try_parse("category", 0usize)?;
-        let subcategory_idx = try_parse("subcategory", 0usize)?;
-
-        if category_idx >= categories.len() {
-            return Err(format!(
-                "Category out of bounds: {} available, got {}",
-                categories.len(),
-                category_idx
-            ));
-        }
+pub fn delay() -> Result<Duration, String> {
+    try_parse("delay", 1000u64).map(Duration::from_millis)
+}
 
-        let subcategories = &categories[category_idx].subcategories;
+/// How failed requests should be retried
+pub fn retry_policy() -> Result<RetryPolicy, String> {
+    let budget = match ARGS.value_of("budget") {
+        None => None,
+        Some(_) => Some(try_parse("budget", 0u32)?),
+    };
+    Ok(RetryPolicy {
+        retries: try_parse("retries", 3u32)?,
+        budget: budget,
+        ..RetryPolicy::default()
+    })
+}
 
-        if subcategories.len() == 0 {
-            if subcategory_idx != 0 {
-                return Err(format!(
-                    "Subcategory must be 0 for categories without subcategories, got {}",
-                    subcategory_idx
-                ));
-            }
-        } else if subcategory_idx >= subcategories.len() {
-            return Err(format!(
-                "Subcategory out of bounds: {} available, for {}",
-                subcategories.len(),
-                subcategory_idx
-            ));
-        }
+/// Path of a saved page to parse instead of fetching one, "-" for stdin
+pub fn input() -> Option<&'static str> {
+    ARGS.value_of("input")
+}
 
-        Ok((category_idx, subcategory_idx))
-    }
+/// Directory where fetched pages should be saved
+pub fn record() -> Option<&'static str> {
+    ARGS.value_of("record")
 }
 
-struct Category {
-    name: &'static str,
-    subcategories: Vec<&'static str>,
+/// Directory of recorded pages to serve instead of fetching pages
+pub fn replay() -> Option<&'static str> {
+    ARGS.value_of("replay")
 }
 
-impl Category {
-    fn from(name: &'static str, subcategories: Vec<&'static str>) -> Category {
-        Category {
-            name: name,
-            subcategories: subcategories,
-        }
-    }
+/// Whether the doctor command was used
+pub fn doctor() -> bool {
+    ARGS.subcommand_matches("doctor").is_some()
+}
 
-    fn names(list: &Vec<Category>) -> String {
-        list.iter()
-            .enumerate()
-            .map(|(index, category)| format!("{} - {}", index, category.name))
-            .collect::<Vec<String>>()
-            .join("\n")
-    }
+pub fn output_json() -> bool {
+    ARGS.is_present("json")
+}
 
-    fn names_and_subcategories(list: &Vec<Category>) -> String {
-        list.iter()
-            .enumerate()
-            .map(|(index, category)| {
-                if category.subcategories.len() == 0 {
-                    format!("{} - {}", index, category.name)
-                } else {
-                    format!(
-                        "{} - {}\n{}",
-                        index,
-                        category.name,
-                        category
-                            .subcategories
-                            .iter()
-                            .enumerate()
-                            .map(|(i, s)| format!("   {} - {}", i + 1, s))
-                            .collect::<Vec<String>>()
-                            .join("\n")
-                    )
-                }
-            })
-            .collect::<Vec<String>>()
-            .join("\n")
-    }
+pub fn rss() -> bool {
+    ARGS.is_present("rss")
 }
 
-pub fn include_amount() -> Result<usize, String> {
-    try_parse("number", 10000000usize)
+pub fn lenient() -> bool {
+    ARGS.is_present("lenient")
 }
 
-pub fn output_json() -> bool {
-    ARGS.is_present("json")
+/// Selector profile loaded from the file given with --selectors, or the
+/// default profile
+pub fn selectors() -> Result<SelectorProfile, String> {
+    match ARGS.value_of("selectors") {
+        None => Ok(SelectorProfile::default()),
+        Some(path) => fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+            .map_err(|e| format!("Invalid selector profile {}: {}", path, e)),
+    }
 }
 
 pub fn should_print(what: &str) -> bool {
@@ -167,51 +134,53 @@ pub fn get_url() -> Result<String, String> {
         return Ok(url.to_owned());
     }
 
+    if let Some(url) = input().and_then(record::recorded_url) {
+        return Ok(url);
+    }
+
     let source = match ARGS.value_of("source") {
-        None => Source::NYAASI,
-        Some("nyaasi") => Source::NYAASI,
-        Some("sukebei") => Source::SUKEBEI,
+        None => Source::Nyaasi,
+        Some("nyaasi") => Source::Nyaasi,
+        Some("sukebei") => Source::Sukebei,
         Some(src) => return Err(format!("Invalid source {}", src)),
     };
-    let filter = try_parse("filter", 2usize)?;
-    let (category, subcategory) = source.parse_categories()?;
-
-    if filter >= FILTERS.len() {
-        return Err(format!(
+    let filter = try_parse("filter", 2u8)?;
+    let filter = Filter::from_code(filter).ok_or_else(|| {
+        format!(
             "Filter out of bounds: {} available, got {}",
-            FILTERS.len(),
+            Filter::ALL.len(),
             filter
-        ));
-    }
+        )
+    })?;
 
-    let mut params = vec![
-        ("f", filter.to_string()),
-        ("c", format!("{}_{}", category, subcategory)),
-        ("p", try_parse("page", 1u64)?.to_string()),
-        ("q", ARGS.value_of("query").unwrap_or("").to_owned()),
-    ];
+    let mut query = SearchQuery::new(source)
+        .filter(filter)
+        .category(Category::new(
+            try_parse("category", 0u8)?,
+            try_parse("subcategory", 0u8)?,
+        ))
+        .page(try_parse("page", 1u32)?)
+        .query(ARGS.value_of("query").unwrap_or(""))
+        .rss(rss());
 
     if let Some(sort) = ARGS.value_of("sort") {
-        if !SORT_KEYS.contains(&sort) {
-            return Err(format!("Invalid sort key {}", sort));
-        }
-        params.push(("s", sort.to_owned()));
+        query = query.sort(sort.parse::<Sort>().map_err(|e| e.to_string())?);
     }
 
     if let Some(order) = ARGS.value_of("order") {
-        if !ORDERING_TYPES.contains(&order) {
-            return Err(format!("Invalid order type {}", order));
-        }
-        params.push(("o", order.to_owned()));
+        query = query.order(order.parse::<Order>().map_err(|e| e.to_string())?);
     }
 
     if let Some(user) = ARGS.value_of("user") {
-        params.push(("u", user.to_owned()));
+        query = query.user(user);
     }
 
-    let base_url = ARGS.value_of("base-url").unwrap_or(source.base_url());
+    if let Some(base_url) = ARGS.value_of("base-url") {
+        query = query.base_url(base_url);
+    }
 
-    Url::parse_with_params(base_url, params.iter())
+    query
+        .to_url()
         .map(|u| u.into_string())
         .map_err(|e| e.to_string())
 }
@@ -241,7 +210,28 @@ fn parse_args() -> ArgMatches<'static> {
             .long("url")
             .value_name("URL")
             .help("Sets the url to be fetched. Overrides all other filtering, sorting, ordering options")
-            .takes_value(true))
+            .takes_value(true)
+            .global(true))
+        .arg(Arg::with_name("input")
+            .long("input")
+            .value_name("FILE")
+            .help("Parses a saved page from FILE, or from stdin if FILE is -, instead of fetching it. Links are resolved against --url, the url saved by --record next to FILE, or the url built from the other options")
+            .takes_value(true)
+            .conflicts_with_all(&["pages", "all", "record"])
+            .global(true))
+        .arg(Arg::with_name("record")
+            .long("record")
+            .value_name("DIR")
+            .help("Saves each fetched page to DIR as <n>.html, with its url, fetch time and HTTP status in <n>.json. Saved pages can be parsed again with --input")
+            .takes_value(true)
+            .global(true))
+        .arg(Arg::with_name("replay")
+            .long("replay")
+            .value_name("DIR")
+            .help("Serves pages recorded with --record in DIR instead of fetching them. Fails if a page with the requested url wasn't recorded")
+            .takes_value(true)
+            .conflicts_with("input")
+            .global(true))
         .arg(Arg::with_name("source")
             .short("S")
             .long("source")
@@ -249,20 +239,23 @@ fn parse_args() -> ArgMatches<'static> {
             .help("Selects the source")
             .takes_value(true)
             .possible_values(&vec!["nyaasi", "sukebei"])
-            .default_value("nyaasi"))
+            .default_value("nyaasi")
+            .global(true))
         .arg(Arg::with_name("base-url")
             .short("b")
             .long("base-url")
             .value_name("BASE")
             .help("Sets the base url to use. Overrides the base url for the selected source")
-            .takes_value(true))
+            .takes_value(true)
+            .global(true))
         .arg(Arg::with_name("filter")
             .short("f")
             .long("filter")
             .value_name("FILTER")
             .help("Sets the filter to apply - 0 is no filter, 1 is no remakes, 2 is trusted")
             .takes_value(true)
-            .default_value("2"))
+            .default_value("2")
+            .global(true))
         .arg(Arg::with_name("category")
             .short("c")
             .long("category")
@@ -270,10 +263,11 @@ fn parse_args() -> ArgMatches<'static> {
             .help("Sets the category wanted")
             .long_help(&format!(
                     "Sets the category wanted\nNyaa.si categories:\n{}\n\nSukebei categories:\n{}",
-                    Category::names(&NYAASI_CATEGORIES),
-                    Category::names(&SUKEBEI_CATEGORIES)
+                    category_names(Source::Nyaasi.categories()),
+                    category_names(Source::Sukebei.categories())
             ))
-            .takes_value(true))
+            .takes_value(true)
+            .global(true))
         .arg(Arg::with_name("subcategory")
             .short("s")
             .long("subcategory")
@@ -281,46 +275,52 @@ fn parse_args() -> ArgMatches<'static> {
             .help("Sets the subcategory wanted")
             .long_help(&format!(
                     "Sets the subcategory wanted\nNyaa.si subcategories:\n{}\n\nSukebei categories:\n{}",
-                    Category::names_and_subcategories(&NYAASI_CATEGORIES),
-                    Category::names_and_subcategories(&SUKEBEI_CATEGORIES)
+                    category_names_and_subcategories(Source::Nyaasi.categories()),
+                    category_names_and_subcategories(Source::Sukebei.categories())
             ))
-            .takes_value(true))
+            .takes_value(true)
+            .global(true))
         .arg(Arg::with_name("query")
             .short("q")
             .long("query")
             .value_name("QUERY")
             .help("Sets the search query")
-            .takes_value(true))
+            .takes_value(true)
+            .global(true))
         .arg(Arg::with_name("user")
             .short("u")
             .long("user")
             .value_name("USER")
             .help("Sets the user to search for (user who uploaded the entry)")
-            .takes_value(true))
+            .takes_value(true)
+            .global(true))
         .arg(Arg::with_name("page")
              .short("p")
              .long("page")
              .value_name("PAGE")
              .help("Sets the page to load")
              .takes_value(true)
-             .default_value("1"))
+             .default_value("1")
+             .global(true))
         .arg(Arg::with_name("sort")
             .long("sort")
             .value_name("KEY")
             .help("Sets the key used for sorting results")
             .takes_value(true)
-            .possible_values(&SORT_KEYS))
+            .possible_values(&SORT_KEYS)
+            .global(true))
         .arg(Arg::with_name("order")
             .long("order")
             .value_name("TYPE")
             .help("Sets the ordering type used")
             .takes_value(true)
-            .possible_values(&ORDERING_TYPES))
+            .possible_values(&ORDERING_TYPES)
+            .global(true))
         .arg(Arg::with_name("include")
             .short("i")
             .long("include")
             .value_name("FIELD")
-            .help("Includes a field when printing to stdout. Ignored if --json is present.\nValid values are url, kind, name, comments, torrent, magnet, size, magnet_size, parsed_size, date, seeders, leechers, downloads, pages, current_page.\nIgnores parsed_size and magnet_size if size is not present.\nIgnores current_page if pages is not set")
+            .help("Includes a field when printing to stdout. Ignored if --json is present.\nValid values are url, id, category, kind, name, comments, torrent, magnet, size, magnet_size, parsed_size, date, seeders, leechers, downloads, pages, current_page.\nIgnores parsed_size and magnet_size if size is not present.\nIgnores current_page if pages is not set")
             .takes_value(true)
             .multiple(true))
         .arg(Arg::with_name("number")
@@ -329,9 +329,52 @@ fn parse_args() -> ArgMatches<'static> {
             .value_name("AMOUNT")
             .help("Number of elements to include. Only the <AMOUNT> most recent ones will be included")
             .takes_value(true))
+        .arg(Arg::with_name("pages")
+            .long("pages")
+            .value_name("PAGES")
+            .help("Number of pages to fetch, starting at --page. Stops earlier if there are no more pages or --number entries were found")
+            .takes_value(true)
+            .conflicts_with("all"))
+        .arg(Arg::with_name("all")
+            .long("all")
+            .help("Fetches all pages, starting at --page. Stops earlier if --number entries were found"))
+        .arg(Arg::with_name("delay")
+            .long("delay")
+            .value_name("MS")
+            .help("Milliseconds to wait between fetching pages")
+            .takes_value(true)
+            .default_value("1000"))
+        .arg(Arg::with_name("retries")
+            .long("retries")
+            .value_name("RETRIES")
+            .help("Number of times a request is retried after a network error or when the site is overloaded or rate limiting (429 and 5xx statuses). The delay between attempts doubles after each retry, or follows the Retry-After header sent by the site")
+            .takes_value(true)
+            .default_value("3")
+            .global(true))
+        .arg(Arg::with_name("budget")
+            .long("budget")
+            .value_name("REQUESTS")
+            .help("Maximum number of requests made to each host, retries included. Fetching stops with an error once it's used up")
+            .takes_value(true)
+            .global(true))
         .arg(Arg::with_name("json")
             .short("j")
             .long("json")
-            .help("Output data as json instead"))
+            .help("Output data as json instead")
+            .global(true))
+        .arg(Arg::with_name("rss")
+            .long("rss")
+            .help("Fetches the RSS feed instead of the search page. The feed is lighter, but has no pagination"))
+        .arg(Arg::with_name("selectors")
+            .long("selectors")
+            .value_name("FILE")
+            .help("Loads the CSS selectors used for parsing from a JSON file, for sites running a modified version of nyaa. Selectors missing from the file keep their default value")
+            .takes_value(true)
+            .global(true))
+        .arg(Arg::with_name("lenient")
+            .long("lenient")
+            .help("Skips rows (or feed items with --rss) that can't be parsed instead of failing. Skipped rows are reported as warnings"))
+        .subcommand(SubCommand::with_name("doctor")
+            .about("Checks which selectors used for parsing still match the page, to diagnose layout changes. The page is chosen with the same options as a search, or with --input. The report can be attached to bug reports"))
         .get_matches()
 }
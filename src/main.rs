@@ -2,31 +2,103 @@
 extern crate lazy_static;
 
 mod args;
+mod record;
 
-fn main() {
-    let limit = match args::include_amount() {
-        Ok(l) => l,
+use nyaasi_scraper::retry::RetryTransport;
+use nyaasi_scraper::transport::{HttpTransport, Transport};
+use nyaasi_scraper::{ParseError, ParseOptions};
+use record::Recorder;
+use std::fs;
+use std::io::{self, Read};
+use std::process;
+use std::thread;
+
+/// Exit code used when the arguments are invalid
+const EXIT_INVALID_ARGS: i32 = 2;
+/// Exit code used when fetching or reading the page fails
+const EXIT_FETCH_FAILED: i32 = 3;
+/// Exit code used when saving a fetched page fails
+const EXIT_RECORD_FAILED: i32 = 4;
+/// Exit code used by the doctor command when the page layout changed
+const EXIT_LAYOUT_CHANGED: i32 = 5;
+
+/// Exit code used when parsing the page fails with an error this version doesn't know about
+const EXIT_PARSE_FAILED: i32 = 1;
+
+/// Exit code used when parsing the page fails, one for each kind of error.
+///
+/// Codes are never reused or renumbered; new kinds of errors get the next unused code.
+fn parse_error_exit_code(e: &ParseError) -> i32 {
+    match e {
+        ParseError::BadPageUrl { .. } => 10,
+        ParseError::MissingTable => 11,
+        ParseError::InvalidSelector { .. } => 12,
+        ParseError::MissingSelector { .. } => 13,
+        ParseError::MissingParent { .. } => 14,
+        ParseError::MissingAttribute { .. } => 15,
+        ParseError::NotAnElement { .. } => 16,
+        ParseError::BadInteger { .. } => 17,
+        ParseError::BadUrlJoin { .. } => 18,
+        ParseError::BadPageNumber { .. } => 19,
+        ParseError::MissingField { .. } => 20,
+        ParseError::MissingEntryKind { .. } => 21,
+        ParseError::NotASearchPage { .. } => 22,
+        ParseError::BadDate { .. } => 23,
+        ParseError::BadCategory { .. } => 24,
+        ParseError::BadXml { .. } => 25,
+        _ => EXIT_PARSE_FAILED,
+    }
+}
+
+/// Fetches a page, exiting if it fails or if the site answers with an error
+/// status. The page is saved if a recorder is given, even with an error status.
+fn fetch(transport: &dyn Transport, url: &str, recorder: &mut Option<Recorder>) -> String {
+    let response = match transport.get(url) {
+        Ok(r) => r,
         Err(e) => {
-            eprintln!("{}", e);
-            return;
+            eprintln!("Failed to fetch data: {}", e);
+            process::exit(EXIT_FETCH_FAILED);
         }
     };
-    let url = match args::get_url() {
-        Ok(u) => u,
-        Err(e) => {
-            eprintln!("{}", e);
-            return;
+    if let Some(recorder) = recorder {
+        if let Err(e) = recorder.save(url, response.status, &response.body) {
+            eprintln!("Failed to record page: {}", e);
+            process::exit(EXIT_RECORD_FAILED);
         }
+    }
+    if !response.is_success() {
+        eprintln!("Failed to fetch {}: status {}", url, response.status);
+        process::exit(EXIT_FETCH_FAILED);
+    }
+    response.body
+}
+
+/// Reads a saved page from a file, or from stdin if the path is "-", exiting
+/// if it can't be read
+fn read_input(path: &str) -> String {
+    let read = if path == "-" {
+        let mut raw = String::new();
+        io::stdin().read_to_string(&mut raw).map(|_| raw)
+    } else {
+        fs::read_to_string(path)
     };
-    let raw = match reqwest::get(&url).and_then(|mut r| r.text()) {
+    match read {
         Ok(r) => r,
         Err(e) => {
-            eprintln!("Failed to fetch data: {}", e);
-            return;
+            eprintln!("Failed to read {}: {}", path, e);
+            process::exit(EXIT_FETCH_FAILED);
         }
-    };
+    }
+}
 
-    let mut data = match nyaasi_scraper::parse_html(&raw, &url) {
+/// Parses a page, exiting if it fails
+fn parse_page(raw: &str, url: &str, options: &ParseOptions) -> nyaasi_scraper::Results {
+    let parsed = if args::rss() {
+        nyaasi_scraper::parse_rss_with_options(raw, options)
+    } else {
+        nyaasi_scraper::parse_html_with_options(raw, url, options)
+    };
+    match parsed {
         Err(e) => {
             eprintln!("{}", e);
             if args::output_json() {
@@ -34,10 +106,104 @@ fn main() {
                     .expect("Failed to serialize results");
                 println!("{}", serialized);
             }
-            return;
+            process::exit(parse_error_exit_code(&e));
         }
         Ok(x) => x,
+    }
+}
+
+fn main() {
+    let limit = match args::include_amount() {
+        Ok(l) => l,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(EXIT_INVALID_ARGS);
+        }
     };
+    let (max_pages, delay) = match (args::max_pages(), args::delay()) {
+        (Ok(m), Ok(d)) => (m, d),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}", e);
+            process::exit(EXIT_INVALID_ARGS);
+        }
+    };
+    let options = match args::selectors() {
+        Ok(selectors) => ParseOptions {
+            lenient: args::lenient(),
+            selectors: selectors,
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(EXIT_INVALID_ARGS);
+        }
+    };
+    let policy = match args::retry_policy() {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(EXIT_INVALID_ARGS);
+        }
+    };
+    let url = match args::get_url() {
+        Ok(u) => u,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(EXIT_INVALID_ARGS);
+        }
+    };
+
+    let mut recorder = match args::record().map(Recorder::new) {
+        None => None,
+        Some(Ok(r)) => Some(r),
+        Some(Err(e)) => {
+            eprintln!("Failed to create record directory: {}", e);
+            process::exit(EXIT_RECORD_FAILED);
+        }
+    };
+
+    let transport: Box<dyn Transport> = match args::replay().map(record::replay) {
+        None => Box::new(RetryTransport::new(HttpTransport::default(), policy)),
+        Some(Ok(t)) => Box::new(t),
+        Some(Err(e)) => {
+            eprintln!("Failed to load replay directory: {}", e);
+            process::exit(EXIT_FETCH_FAILED);
+        }
+    };
+
+    let raw = match args::input() {
+        Some(path) => read_input(path),
+        None => fetch(&*transport, &url, &mut recorder),
+    };
+
+    if args::doctor() {
+        let report = nyaasi_scraper::doctor::diagnose_with(&options.selectors, &raw, &url);
+        if args::output_json() {
+            let serialized = serde_json::to_string(&report).expect("Failed to serialize report");
+            println!("{}", serialized);
+        } else {
+            println!("{}", report);
+        }
+        if !report.is_healthy() {
+            process::exit(EXIT_LAYOUT_CHANGED);
+        }
+        return;
+    }
+
+    let mut data = parse_page(&raw, &url, &options);
+    let mut fetched = 1;
+    loop {
+        if data.entries.len() >= limit || max_pages == Some(fetched) {
+            break;
+        }
+        let next = match data.pagination.as_ref().and_then(|p| p.next.as_ref()) {
+            Some(next) => next.url.clone(),
+            None => break,
+        };
+        thread::sleep(delay);
+        let raw = fetch(&*transport, &next, &mut recorder);
+        data.merge(parse_page(&raw, &next, &options));
+        fetched += 1;
+    }
 
     data.entries = data
         .entries
@@ -53,6 +219,12 @@ fn main() {
         let serialized = serde_json::to_string(&data).expect("Failed to serialize results");
         println!("{}", serialized);
     } else {
+        for warning in data.warnings.iter() {
+            eprintln!("Skipped row {}: {}", warning.row, warning.error);
+        }
+        if data.entries.is_empty() {
+            eprintln!("No results found");
+        }
         for row in data.entries.iter() {
             if args::should_print("name") {
                 println!("{}", row.name);
@@ -60,6 +232,12 @@ fn main() {
             if args::should_print("url") {
                 println!("\tURL:        {}", row.url);
             }
+            if args::should_print("id") {
+                println!("\tID:         {}", row.id);
+            }
+            if args::should_print("category") {
+                println!("\tCategory:   {}_{}", row.category.0, row.category.1);
+            }
             if args::should_print("kind") {
                 println!("\tKind:       {:?}", row.kind);
             }
@@ -118,6 +296,11 @@ fn main() {
                     }
                 }
                 print!("\n");
+                if let (Some(first), Some(last), Some(total)) =
+                    (p.first_index, p.last_index, p.total_results)
+                {
+                    println!("Results: {}-{} of {}", first, last, total);
+                }
             }
         }
     }
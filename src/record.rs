@@ -0,0 +1,182 @@
+use nyaasi_scraper::transport::{MockTransport, Response};
+use serde_json::json;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Saves fetched pages to a directory, so they can be parsed again with
+/// --input or used as test fixtures.
+///
+/// Each page is saved as `<n>.html`, next to a `<n>.json` file holding the
+/// url, fetch time (unix timestamp in seconds) and HTTP status of the page.
+pub struct Recorder {
+    dir: PathBuf,
+    next: usize,
+}
+
+impl Recorder {
+    /// Creates the directory if needed. Numbering continues after the pages
+    /// already saved there.
+    pub fn new(dir: &str) -> io::Result<Recorder> {
+        fs::create_dir_all(dir)?;
+        let saved = fs::read_dir(dir)?
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let path = e.path();
+                if path.extension() == Some(OsStr::new("html")) {
+                    path.file_stem()?.to_str()?.parse::<usize>().ok()
+                } else {
+                    None
+                }
+            })
+            .max()
+            .unwrap_or(0);
+        Ok(Recorder {
+            dir: PathBuf::from(dir),
+            next: saved + 1,
+        })
+    }
+
+    /// Saves a page and its metadata
+    pub fn save(&mut self, url: &str, status: u16, body: &str) -> io::Result<()> {
+        let fetched_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let meta = json!({
+            "url": url,
+            "fetched_at": fetched_at,
+            "status": status,
+        });
+
+        let html = self.dir.join(format!("{}.html", self.next));
+        fs::write(&html, body)?;
+        fs::write(
+            html.with_extension("json"),
+            serde_json::to_string_pretty(&meta).expect("Failed to serialize metadata"),
+        )?;
+        self.next += 1;
+        Ok(())
+    }
+}
+
+/// Reads the metadata saved next to a recorded page
+fn read_meta(html: &Path) -> Option<serde_json::Value> {
+    let meta = fs::read_to_string(html.with_extension("json")).ok()?;
+    serde_json::from_str(&meta).ok()
+}
+
+/// Returns the url saved next to a recorded page, if there is one
+pub fn recorded_url(html: &str) -> Option<String> {
+    let meta = read_meta(Path::new(html))?;
+    meta.get("url")?.as_str().map(|u| u.to_owned())
+}
+
+/// Loads the pages recorded in a directory into a transport that serves
+/// each page for its url. Pages without a url are ignored, and pages
+/// without a status are served with status 200.
+pub fn replay(dir: &str) -> io::Result<MockTransport> {
+    let mut transport = MockTransport::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension() != Some(OsStr::new("html")) {
+            continue;
+        }
+        let meta = match read_meta(&path) {
+            Some(meta) => meta,
+            None => continue,
+        };
+        let url = match meta.get("url").and_then(|u| u.as_str()) {
+            Some(url) => url,
+            None => continue,
+        };
+        let status = meta.get("status").and_then(|s| s.as_u64()).unwrap_or(200);
+        transport.insert(
+            url,
+            Response::new(status as u16, &fs::read_to_string(&path)?),
+        );
+    }
+    Ok(transport)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nyaasi_scraper::transport::Transport;
+    use std::env;
+    use std::process;
+
+    /// Creates an empty directory for a test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("nyaasi-record-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn read_meta_file(path: &Path) -> serde_json::Value {
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_save() {
+        let dir = temp_dir("save");
+        let mut recorder = Recorder::new(dir.to_str().unwrap()).unwrap();
+        recorder
+            .save("https://nyaa.si/?q=a", 200, "<p>a</p>")
+            .unwrap();
+        recorder
+            .save("https://nyaa.si/?q=b", 503, "<p>b</p>")
+            .unwrap();
+
+        assert_eq!(fs::read_to_string(dir.join("1.html")).unwrap(), "<p>a</p>");
+        assert_eq!(fs::read_to_string(dir.join("2.html")).unwrap(), "<p>b</p>");
+        let meta = read_meta_file(&dir.join("2.json"));
+        assert_eq!(meta["url"], "https://nyaa.si/?q=b");
+        assert_eq!(meta["status"], 503);
+        assert!(meta["fetched_at"].as_u64().unwrap() > 0);
+
+        // a new recorder continues after the saved pages
+        let mut recorder = Recorder::new(dir.to_str().unwrap()).unwrap();
+        recorder
+            .save("https://nyaa.si/?q=c", 200, "<p>c</p>")
+            .unwrap();
+        assert_eq!(fs::read_to_string(dir.join("3.html")).unwrap(), "<p>c</p>");
+        assert_eq!(
+            read_meta_file(&dir.join("3.json"))["url"],
+            "https://nyaa.si/?q=c"
+        );
+    }
+
+    #[test]
+    fn test_replay() {
+        let dir = temp_dir("replay");
+        let mut recorder = Recorder::new(dir.to_str().unwrap()).unwrap();
+        recorder
+            .save("https://nyaa.si/?q=a", 200, "<p>a</p>")
+            .unwrap();
+        recorder
+            .save("https://nyaa.si/?q=b", 404, "<p>b</p>")
+            .unwrap();
+        // pages without metadata are ignored
+        fs::write(dir.join("3.html"), "<p>c</p>").unwrap();
+
+        let page = dir.join("1.html");
+        assert_eq!(
+            recorded_url(page.to_str().unwrap()).as_deref(),
+            Some("https://nyaa.si/?q=a")
+        );
+        assert_eq!(recorded_url(dir.join("3.html").to_str().unwrap()), None);
+
+        let transport = replay(dir.to_str().unwrap()).unwrap();
+        let response = transport.get("https://nyaa.si/?q=b").unwrap();
+        assert_eq!(response.status, 404);
+        assert_eq!(response.body, "<p>b</p>");
+        assert_eq!(
+            transport.get("https://nyaa.si/?q=a").unwrap().body,
+            "<p>a</p>"
+        );
+        assert!(transport.get("https://nyaa.si/?q=c").is_err());
+    }
+}
@@ -0,0 +1,223 @@
+//! End to end tests of the command line tool. Pages are served from
+//! recordings with `--replay` or read with `--input`, so no requests are made.
+
+use serde_json::{json, Value};
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+/// Url the search fixture was recorded from
+const SEARCH_URL: &str = "https://nyaa.si/?f=0&c=0_0&q=show&p=2";
+
+fn fixtures() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("nyaasi-scraper")
+        .join("tests")
+        .join("fixtures")
+}
+
+fn search_html() -> String {
+    fs::read_to_string(fixtures().join("search.html")).unwrap()
+}
+
+/// Results the search fixture should parse to
+fn search_expected() -> Value {
+    serde_json::from_str(&fs::read_to_string(fixtures().join("search.expected.json")).unwrap())
+        .unwrap()
+}
+
+/// Creates an empty directory to record pages in
+fn replay_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("nyaasi-cli-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Saves a page to a replay directory, the way --record does
+fn record(dir: &Path, n: usize, url: &str, status: u16, body: &str) {
+    fs::write(dir.join(format!("{}.html", n)), body).unwrap();
+    let meta = json!({ "url": url, "fetched_at": 0, "status": status });
+    fs::write(dir.join(format!("{}.json", n)), meta.to_string()).unwrap();
+}
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_nyaasi"))
+        .args(args)
+        .output()
+        .expect("Failed to run nyaasi")
+}
+
+fn run_with_stdin(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_nyaasi"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run nyaasi");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().expect("Failed to run nyaasi")
+}
+
+fn stdout_json(output: &Output) -> Value {
+    assert!(
+        output.status.success(),
+        "nyaasi failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn test_replay_url() {
+    let fixtures = fixtures();
+    let output = run(&[
+        "--replay",
+        fixtures.to_str().unwrap(),
+        "--url",
+        SEARCH_URL,
+        "--json",
+    ]);
+    assert_eq!(stdout_json(&output), search_expected());
+}
+
+#[test]
+fn test_input_file() {
+    // the url is read from the metadata saved next to the page
+    let page = fixtures().join("search.html");
+    let output = run(&["--input", page.to_str().unwrap(), "--json"]);
+    assert_eq!(stdout_json(&output), search_expected());
+}
+
+#[test]
+fn test_input_stdin() {
+    let output = run_with_stdin(
+        &["--input", "-", "--url", SEARCH_URL, "--json"],
+        &search_html(),
+    );
+    assert_eq!(stdout_json(&output), search_expected());
+}
+
+#[test]
+fn test_record_replay() {
+    // record the page served from the fixtures, then replay the recording
+    let dir = replay_dir("record");
+    let fixtures = fixtures();
+    let recorded = run(&[
+        "--replay",
+        fixtures.to_str().unwrap(),
+        "--record",
+        dir.to_str().unwrap(),
+        "--url",
+        SEARCH_URL,
+        "--json",
+    ]);
+    assert_eq!(stdout_json(&recorded), search_expected());
+    assert_eq!(
+        fs::read_to_string(dir.join("1.html")).unwrap(),
+        search_html()
+    );
+    let meta: Value =
+        serde_json::from_str(&fs::read_to_string(dir.join("1.json")).unwrap()).unwrap();
+    assert_eq!(meta["url"], SEARCH_URL);
+    assert_eq!(meta["status"], 200);
+
+    let replayed = run(&[
+        "--replay",
+        dir.to_str().unwrap(),
+        "--url",
+        SEARCH_URL,
+        "--json",
+    ]);
+    assert_eq!(stdout_json(&replayed), search_expected());
+}
+
+#[test]
+fn test_built_url() {
+    let dir = replay_dir("built");
+    record(
+        &dir,
+        1,
+        "https://nyaa.si/?f=0&c=0_0&p=2&q=show",
+        200,
+        &search_html(),
+    );
+
+    let output = run(&[
+        "--replay",
+        dir.to_str().unwrap(),
+        "-f",
+        "0",
+        "-q",
+        "show",
+        "-p",
+        "2",
+        "-i",
+        "name",
+        "-i",
+        "seeders",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 6);
+    assert!(lines[1].starts_with("\tSeeders:"));
+}
+
+#[test]
+fn test_pages() {
+    let dir = replay_dir("pages");
+    record(&dir, 1, SEARCH_URL, 200, &search_html());
+    record(
+        &dir,
+        2,
+        "https://nyaa.si/?f=0&c=0_0&q=show&p=3",
+        200,
+        &search_html(),
+    );
+
+    let output = run(&[
+        "--replay",
+        dir.to_str().unwrap(),
+        "--url",
+        SEARCH_URL,
+        "--pages",
+        "2",
+        "--delay",
+        "0",
+        "--json",
+    ]);
+    let results = stdout_json(&output);
+    assert_eq!(results["entries"].as_array().unwrap().len(), 6);
+}
+
+#[test]
+fn test_missing_page() {
+    let dir = replay_dir("missing");
+    let output = run(&["--replay", dir.to_str().unwrap(), "-q", "show"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No response available"));
+}
+
+#[test]
+fn test_error_status() {
+    let dir = replay_dir("status");
+    record(
+        &dir,
+        1,
+        SEARCH_URL,
+        503,
+        "<html><title>503 Service Unavailable</title></html>",
+    );
+    let output = run(&["--replay", dir.to_str().unwrap(), "--url", SEARCH_URL]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("status 503"));
+}